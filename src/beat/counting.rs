@@ -8,28 +8,33 @@ use fugit::Instant;
 
 use crate::{rgbs_issue_beat, SHARED};
 
-use super::{BeatCount, SHOOT_NOW_SIGNAL};
+use super::{
+    schedule::Schedule, tapping::SHOOT_NOW_SIGNAL, BeatCount, Swing, TimeSignature,
+    DEFAULT_PHRASE_LENGTH,
+};
 
 static LAST_SHOT: Mutex<RefCell<Option<Instant<u64, 1, 1000000>>>> = Mutex::new(RefCell::new(None));
-const MIN_TIME_PIECE: usize = 32;
 
 #[embassy_executor::task]
 pub async fn beat_executor() {
     let mut is_repeating = false;
-    let mut interval = 0;
-    let mut last_loop_process_time = 0;
 
-    let mut beat_count = BeatCount::default();
+    let mut signature = TimeSignature::default();
+    let mut swing = Swing::default();
+    let mut rate = 1.0;
+    let mut phrase_length = DEFAULT_PHRASE_LENGTH;
+    let mut beat_count = BeatCount::new(signature, phrase_length);
+    let mut schedule = Schedule::new(0, 0);
 
     loop {
         let input_signal = SHOOT_NOW_SIGNAL.wait();
         let mut is_signaled = false;
         if is_repeating {
-            let either = select(
-                input_signal,
-                Timer::after_micros(interval - last_loop_process_time),
-            )
-            .await;
+            // wait until the absolute time of the next tick
+            let next_tick_time = schedule.tick_time(beat_count.tick + 1, &beat_count, &swing);
+            let wait = next_tick_time.saturating_sub(current_time().ticks());
+
+            let either = select(input_signal, Timer::after_micros(wait)).await;
 
             if let Either::First(_) = either {
                 is_signaled = true;
@@ -40,42 +45,91 @@ pub async fn beat_executor() {
             is_signaled = true;
         }
 
-        let process_start_time = current_time();
-
         // either start at 1 or increment the counting measure
         if is_signaled {
-            beat_count = BeatCount::default();
+            beat_count = BeatCount::new(signature, phrase_length, rate);
+            schedule = Schedule::new(current_time().ticks(), schedule.quarter());
         } else {
-            beat_count.increment();
+            advance(
+                &mut beat_count,
+                &mut schedule,
+                signature,
+                phrase_length,
+                rate,
+            );
         }
 
+        let mut shift = 0;
         critical_section::with(|cs| {
             let mut shared = SHARED.borrow_ref_mut(cs);
             let tap_info = shared.tap_info.as_mut();
             if let Some(info) = tap_info {
+                signature = info.signature;
+                swing = info.swing;
+                phrase_length = info.phrase_length;
+                // a nudge moves the whole grid
+                shift = core::mem::take(&mut info.phase_shift);
+                schedule.shift(shift);
+
                 if info.is_stopped {
                     is_repeating = false;
-                } else if let Some(interv) = info.interval {
+                } else if let Some(interval) = info.interval {
+                    // the schedule stays on the tapped tempo, the rate only changes the
+                    // ticks per bar from the next bar on, so that the bars stay aligned
+                    // with the music
+                    rate = info.rate;
+
                     // the tapping interval is expected as quarters of a bar
-                    // thus we wait for the smallest time piece used in the system
-                    // instead of waiting for quarters
-                    interval = interv / (MIN_TIME_PIECE / 4) as u64;
+                    if interval != schedule.quarter() {
+                        schedule.set_quarter(interval, beat_count.tick, &beat_count);
+                    }
                     is_repeating = true;
                 }
             }
         });
 
-        let last_shot = critical_section::with(|cs| {
-            LAST_SHOT
-                .borrow_ref_mut(cs)
-                .unwrap_or(Instant::<u64, 1, 1000000>::from_ticks(0))
-        });
-        let current_t = current_time();
-        log::info!("Shoot triggered! {:?}", current_t - last_shot);
-        critical_section::with(|cs| LAST_SHOT.borrow_ref_mut(cs).replace(current_t));
+        // a nudge to earlier moves ticks into the past, they are skipped instead of being
+        // fired all at once
+        if shift < 0 && is_repeating {
+            let now = current_time().ticks();
+            while schedule.tick_time(beat_count.tick + 1, &beat_count, &swing) <= now {
+                advance(
+                    &mut beat_count,
+                    &mut schedule,
+                    signature,
+                    phrase_length,
+                    rate,
+                );
+            }
+        }
+
+        // most ticks are only there for the finer grids, skip those without any trigger
+        if beat_count.is_triggered() {
+            let last_shot = critical_section::with(|cs| {
+                LAST_SHOT
+                    .borrow_ref_mut(cs)
+                    .unwrap_or(Instant::<u64, 1, 1000000>::from_ticks(0))
+            });
+            let current_t = current_time();
+            log::info!("Shoot triggered! {:?}", current_t - last_shot);
+            critical_section::with(|cs| LAST_SHOT.borrow_ref_mut(cs).replace(current_t));
 
-        rgbs_issue_beat(&beat_count);
+            rgbs_issue_beat(&beat_count);
+        }
+    }
+}
 
-        last_loop_process_time = (current_time() - process_start_time).to_micros();
+// moves the count on by a tick, the schedule follows at the start of a new bar
+fn advance(
+    beat_count: &mut BeatCount,
+    schedule: &mut Schedule,
+    signature: TimeSignature,
+    phrase_length: usize,
+    rate: f32,
+) {
+    let ticks_per_bar = beat_count.signature.ticks_per_bar();
+    beat_count.increment(signature, phrase_length, rate);
+    if beat_count.tick == 0 {
+        schedule.next_bar(ticks_per_bar);
     }
 }
@@ -1,22 +1,156 @@
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use anyhow::anyhow;
 
-pub mod counting;
-pub mod tapping;
+use crate::patterns::command::parse;
 
-static SHOOT_NOW_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+pub mod schedule;
 
-/// Structure to signal the position of a beat trigger in a 4/4 music environment.
-/// for each field, if it is `None`, it is not triggered, while if it is `Some(n)`, then
-/// it is triggering and `n` tells about the position in the current 4/4 bar.
+// resolution of the beat counting; divisible by 8 for 32nd notes and by 6 for 16th triplets
+pub const TICKS_PER_QUARTER: usize = 24;
+
+// number of bars in a phrase, after which the music usually changes (e.g. a drop)
+pub const PHRASE_LENGTHS: [usize; 4] = [4, 8, 16, 32];
+pub const DEFAULT_PHRASE_LENGTH: usize = 8;
+
+/// Time signature of the music, e.g. 3/4 or 6/8.
+///
+/// The tapped tempo always gives the length of a quarter note, so a 6/8 bar is as long as
+/// a 3/4 bar and a 7/8 bar lasts three and a half quarters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub beats: usize,      // number of notes per bar
+    pub note_value: usize, // note value of the beats (4: quarter notes, 8: eighth notes)
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            beats: 4,
+            note_value: 4,
+        }
+    }
+}
+
+impl TimeSignature {
+    /// Length of a bar in ticks.
+    pub fn ticks_per_bar(&self) -> usize {
+        self.beats * 4 * TICKS_PER_QUARTER / self.note_value
+    }
+}
+
+impl TryFrom<&str> for TimeSignature {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (beats, note_value) = value
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Invalid time signature {:?}. Use e.g. 3/4 or 6/8", value))?;
+        let beats = parse::<usize>(beats)?;
+        let note_value = parse::<usize>(note_value)?;
+
+        if !(1..=32).contains(&beats) {
+            return Err(anyhow!("A bar needs 1 to 32 beats, got {}", beats));
+        }
+        if ![1, 2, 4, 8, 16].contains(&note_value) {
+            return Err(anyhow!(
+                "Invalid note value {}. Use 1, 2, 4, 8 or 16",
+                note_value
+            ));
+        }
+
+        Ok(Self { beats, note_value })
+    }
+}
+
+/// Swing of the off-beat 8th or 16th notes.
+///
+/// `amount` is the share (in percent) of a pair of notes that the first note gets: 50 is
+/// straight, 66 is a triplet feel and 75 is a dotted rhythm.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Swing {
+    pub amount: u8,
+    pub note_ticks: usize, // length of the swung note in ticks
+}
+
+impl Default for Swing {
+    fn default() -> Self {
+        Self {
+            amount: 50,
+            note_ticks: TICKS_PER_QUARTER / 2,
+        }
+    }
+}
+
+impl Swing {
+    /// Returns the swung position (in fractional ticks) of a straight tick in a bar.
+    ///
+    /// Pairs of notes that don't fit into the end of the bar stay straight, so that the
+    /// length of a bar is never changed.
+    pub fn warp(&self, tick: usize, ticks_per_bar: usize) -> f32 {
+        let pair = 2 * self.note_ticks;
+        let start = tick - tick % pair;
+        if start + pair > ticks_per_bar {
+            return tick as f32;
+        }
+
+        let split = pair as f32 * self.amount as f32 / 100.0;
+        let (pos, note) = ((tick - start) as f32, self.note_ticks as f32);
+        let swung = if pos <= note {
+            pos * split / note
+        } else {
+            split + (pos - note) * (pair as f32 - split) / note
+        };
+
+        start as f32 + swung
+    }
+}
+
+impl TryFrom<&str> for Swing {
+    type Error = anyhow::Error;
+
+    /// Parses "off" or "<amount in %>" with an optional note value ("8" or "16").
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        if value == "off" {
+            return Ok(Self::default());
+        }
+
+        let (amount, note) = value.split_once(' ').unwrap_or((value, "8"));
+        let amount = parse::<u8>(amount)?;
+        if !(50..=75).contains(&amount) {
+            return Err(anyhow!("Swing {}% is not in 50..=75%", amount));
+        }
+
+        let note_ticks = match note {
+            "8" => TICKS_PER_QUARTER / 2,
+            "16" => TICKS_PER_QUARTER / 4,
+            n => return Err(anyhow!("Invalid swing note value {:?}. Use 8 or 16", n)),
+        };
+
+        Ok(Self { amount, note_ticks })
+    }
+}
+
+/// Structure to signal the position of a beat trigger in a bar of the current time
+/// signature. For each field, if it is `None`, it is not triggered, while if it is
+/// `Some(n)`, then it is triggering and `n` tells about the position in the current bar.
 ///
 /// All measures are zero indexed for easier handling.
 ///
 /// * `n_full`: Only `Some(0)` if the very first beat of the current bar is triggered.
-/// * `n_half`: Triggers twice per bar.
-/// * `n_quarter`: Triggers four times per bar.
-/// * `n8th`: Triggers eight times per bar.
-/// * `n16th`: Triggers 16 times per bar.
-/// * `n32th`: Triggers 32 times per bar.
+/// * `n_half`: Triggers twice per bar, at the start and in the middle of the bar.
+/// * `n_quarter`: Triggers on every quarter note of the bar (four times in 4/4, three
+///   times in 3/4 or 6/8).
+/// * `n8th`: Triggers on every eighth note of the bar.
+/// * `n16th`: Triggers on every 16th note of the bar.
+/// * `n32th`: Triggers on every 32nd note of the bar.
+/// * `n8th_triplet`: Triggers three times per quarter note.
+/// * `n16th_triplet`: Triggers six times per quarter note.
+/// * `tick`: Position in the bar in ticks (24 per quarter note at a rate of 1x).
+/// * `bar`: Number of bars of the music since the counting was (re)started by a tap or a
+///   downbeat.
+/// * `phrase_length`: Number of bars per phrase (4, 8, 16 or 32).
+/// * `signature`: Time signature of the current bar.
+/// * `rate`: Speed of the note grids relative to the tempo. A bar keeps its length in
+///   time, at 2x it just holds twice as many ticks (and notes).
 #[derive(Debug, Copy, Clone)]
 pub struct BeatCount {
     pub n_full: Option<usize>,
@@ -24,52 +158,93 @@ pub struct BeatCount {
     pub n_quarter: Option<usize>,
     pub n8th: Option<usize>,
     pub n16th: Option<usize>,
-    pub n32th: usize,
+    pub n32th: Option<usize>,
+    pub n8th_triplet: Option<usize>,
+    pub n16th_triplet: Option<usize>,
+    pub tick: usize,
+    pub bar: usize,
+    pub phrase_length: usize,
+    pub signature: TimeSignature,
+    pub rate: f32,
 }
 
 impl Default for BeatCount {
     fn default() -> Self {
+        Self::new(TimeSignature::default(), DEFAULT_PHRASE_LENGTH, 1.0)
+    }
+}
+
+impl BeatCount {
+    /// Creates the count of the first beat of a phrase.
+    pub fn new(signature: TimeSignature, phrase_length: usize, rate: f32) -> Self {
         Self {
             n_full: Some(0),
             n_half: Some(0),
             n_quarter: Some(0),
             n8th: Some(0),
             n16th: Some(0),
-            n32th: 0,
+            n32th: Some(0),
+            n8th_triplet: Some(0),
+            n16th_triplet: Some(0),
+            tick: 0,
+            bar: 0,
+            phrase_length,
+            signature,
+            rate,
         }
     }
-}
 
-impl BeatCount {
-    pub fn increment(&mut self) {
+    /// Moves on by a tick. A changed time signature, phrase length or rate only takes
+    /// effect at the start of the next bar, so that the current bar is counted to its end
+    /// and the bars stay aligned with the music.
+    pub fn increment(&mut self, signature: TimeSignature, phrase_length: usize, rate: f32) {
         // increment only the lowest counter
-        self.n32th = (self.n32th + 1) % 32;
+        self.tick += 1;
+        if self.tick >= self.ticks_in_bar() {
+            self.tick = 0;
+            self.bar = self.bar.wrapping_add(1);
+            self.signature = signature;
+            self.phrase_length = phrase_length;
+            self.rate = rate;
+        }
 
         // then update the other fields from there
-        self.n16th = if self.n32th % 2 == 0 {
-            Some(self.n32th / 2)
-        } else {
-            None
-        };
-        self.n8th = if self.n32th % 4 == 0 {
-            Some(self.n32th / 4)
-        } else {
-            None
-        };
-        self.n_quarter = if self.n32th % 8 == 0 {
-            Some(self.n32th / 8)
-        } else {
-            None
-        };
-        self.n_half = if self.n32th % 16 == 0 {
-            Some(self.n32th / 16)
-        } else {
-            None
-        };
-        self.n_full = if self.n32th % 32 == 0 {
-            Some(self.n32th / 32)
-        } else {
-            None
+        let position = |ticks: usize| {
+            if self.tick % ticks == 0 {
+                Some(self.tick / ticks)
+            } else {
+                None
+            }
         };
+
+        self.n32th = position(TICKS_PER_QUARTER / 8);
+        self.n16th = position(TICKS_PER_QUARTER / 4);
+        self.n8th = position(TICKS_PER_QUARTER / 2);
+        self.n_quarter = position(TICKS_PER_QUARTER);
+        self.n8th_triplet = position(TICKS_PER_QUARTER / 3);
+        self.n16th_triplet = position(TICKS_PER_QUARTER / 6);
+        self.n_half = position(self.signature.ticks_per_bar() / 2);
+        self.n_full = position(self.signature.ticks_per_bar());
+    }
+
+    /// Number of ticks in the current bar at the current rate. At rates like 1.5x the last
+    /// tick is cut short by the end of the bar.
+    pub fn ticks_in_bar(&self) -> usize {
+        libm::ceilf(self.signature.ticks_per_bar() as f32 * self.rate) as usize
+    }
+
+    /// Position of the current bar in its phrase (zero indexed).
+    pub fn bar_in_phrase(&self) -> usize {
+        self.bar % self.phrase_length
+    }
+
+    /// Whether the current tick is the very first one of a phrase.
+    pub fn is_phrase_start(&self) -> bool {
+        self.tick == 0 && self.bar_in_phrase() == 0
+    }
+
+    /// Whether any of the note grids triggers at the current tick.
+    pub fn is_triggered(&self) -> bool {
+        self.n32th.is_some() || self.n16th_triplet.is_some()
     }
 }
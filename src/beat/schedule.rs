@@ -0,0 +1,193 @@
+//! Absolute timing of the beat ticks.
+//!
+//! Every tick time is calculated from an anchor time and the number of ticks since then,
+//! instead of adding up tick lengths. Rounding errors and late wake ups therefore never
+//! add up and the grid stays in phase with the music for any length of time.
+//!
+//! The schedule itself always runs on the tapped tempo. A rate multiplier only changes
+//! how many ticks of the patterns' grid fall into a bar, so every bar still starts in
+//! phase with the music.
+//!
+//! This module is plain arithmetic without any hardware access.
+
+use super::{BeatCount, Swing, TICKS_PER_QUARTER};
+
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    anchor: i64,      // time of the anchor tick in µs
+    anchor_tick: u64, // number of the tick at the anchor time
+    bar_start: u64,   // number of the first tick of the current bar
+    quarter: u64,     // length of a quarter note in µs
+}
+
+impl Schedule {
+    /// Starts a schedule whose first bar begins at `start` (in µs).
+    pub fn new(start: u64, quarter: u64) -> Self {
+        Self {
+            anchor: start as i64,
+            anchor_tick: 0,
+            bar_start: 0,
+            quarter,
+        }
+    }
+
+    pub fn quarter(&self) -> u64 {
+        self.quarter
+    }
+
+    /// Returns the time (in µs) of the given tick of the current bar, counted at the rate
+    /// of `count`. `tick` may be `count.ticks_in_bar()`, which is the start of the next
+    /// bar.
+    pub fn tick_time(&self, tick: usize, count: &BeatCount, swing: &Swing) -> u64 {
+        // the swing stays within a note pair, so it can't add up
+        let swung = swing.warp(tick, count.ticks_in_bar());
+
+        // position in ticks of the tempo; a last tick cut short by the rate ends the bar
+        let position = (swung / count.rate).min(count.signature.ticks_per_bar() as f32);
+        let whole = position as u64;
+        let fraction = (position - whole as f32) * self.quarter as f32 / TICKS_PER_QUARTER as f32;
+
+        (self.straight_time(self.bar_start + whole) + fraction as i64).max(0) as u64
+    }
+
+    /// Moves on to the next bar, `ticks_per_bar` is the length of the finished bar.
+    pub fn next_bar(&mut self, ticks_per_bar: usize) {
+        self.bar_start += ticks_per_bar as u64;
+    }
+
+    /// Changes the tempo from the given tick (counted at the rate of `count`) of the
+    /// current bar on. The ticks before keep their times, so the grid continues without
+    /// a jump.
+    pub fn set_quarter(&mut self, quarter: u64, tick: usize, count: &BeatCount) {
+        // the tick of the tempo at or before the given one
+        let anchor_tick = self.bar_start + (tick as f32 / count.rate) as u64;
+
+        self.anchor = self.straight_time(anchor_tick);
+        self.anchor_tick = anchor_tick;
+        self.quarter = quarter;
+    }
+
+    /// Moves the whole grid by the given µs (> 0: later).
+    pub fn shift(&mut self, micros: i64) {
+        self.anchor += micros;
+    }
+
+    // time of a tick without swing
+    fn straight_time(&self, tick: u64) -> i64 {
+        let ticks = tick - self.anchor_tick;
+        self.anchor + (ticks * self.quarter / TICKS_PER_QUARTER as u64) as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat::{TimeSignature, DEFAULT_PHRASE_LENGTH};
+
+    const TICKS_PER_BAR: usize = 4 * TICKS_PER_QUARTER;
+
+    #[test]
+    fn bars_never_drift() {
+        // a quarter that isn't divisible by the ticks per quarter, so every tick is rounded
+        let quarter = 500_011;
+        let mut schedule = Schedule::new(1_000, quarter);
+        let straight = Swing::default();
+        let count = BeatCount::default();
+
+        for bar in 0..10_000 {
+            let start = 1_000 + bar * 4 * quarter;
+            assert_eq!(schedule.tick_time(0, &count, &straight), start);
+            assert_eq!(
+                schedule.tick_time(TICKS_PER_BAR, &count, &straight),
+                start + 4 * quarter
+            );
+            schedule.next_bar(TICKS_PER_BAR);
+        }
+    }
+
+    #[test]
+    fn new_tempo_continues_the_grid() {
+        let straight = Swing::default();
+        let count = BeatCount::default();
+        let mut schedule = Schedule::new(0, 500_011);
+        schedule.next_bar(TICKS_PER_BAR);
+
+        let tick = 37;
+        let before = schedule.tick_time(tick, &count, &straight);
+        schedule.set_quarter(400_007, tick, &count);
+
+        assert_eq!(schedule.tick_time(tick, &count, &straight), before);
+        assert_eq!(
+            schedule.tick_time(tick + TICKS_PER_QUARTER, &count, &straight),
+            before + 400_007
+        );
+    }
+
+    #[test]
+    fn swing_stays_inside_a_note_pair() {
+        let quarter = 500_011;
+        let schedule = Schedule::new(0, quarter);
+        let straight = Swing::default();
+        let count = BeatCount::default();
+        let swing = Swing {
+            amount: 66,
+            note_ticks: TICKS_PER_QUARTER / 2,
+        };
+
+        let pair = 2 * swing.note_ticks;
+        for tick in 0..=TICKS_PER_BAR {
+            let start = tick - tick % pair;
+            let swung = schedule.tick_time(tick, &count, &swing);
+
+            assert!(swung >= schedule.tick_time(start, &count, &straight));
+            assert!(swung <= schedule.tick_time(start + pair, &count, &straight));
+            if tick % pair == 0 {
+                assert_eq!(swung, schedule.tick_time(tick, &count, &straight));
+            }
+        }
+
+        // the first note of a pair gets 66% of it (up to the rounding of the f32 offset)
+        let second_note = schedule.tick_time(swing.note_ticks, &count, &swing);
+        assert!(second_note.abs_diff(quarter * 66 / 100) <= 1);
+    }
+
+    #[test]
+    fn rates_keep_the_bars_in_phase() {
+        let quarter = 500_011;
+        let mut schedule = Schedule::new(0, quarter);
+        let straight = Swing::default();
+
+        // at 2x a tick lasts half a tick of the tempo
+        let double = BeatCount::new(TimeSignature::default(), DEFAULT_PHRASE_LENGTH, 2.0);
+        assert_eq!(
+            schedule.tick_time(2, &double, &straight),
+            schedule.tick_time(1, &BeatCount::default(), &straight)
+        );
+
+        // the rate changes at the end of a bar, after any number of bars at any rate the
+        // next bar starts in phase with the tempo
+        let rates = [2.0, 2.0, 2.0, 1.0, 1.5, 0.25, 3.0, 1.0];
+        let mut count = BeatCount::new(TimeSignature::default(), DEFAULT_PHRASE_LENGTH, rates[0]);
+        for (bar, &next_rate) in rates[1..].iter().enumerate() {
+            assert_eq!((count.bar, count.tick), (bar, 0));
+            assert_eq!(
+                schedule.tick_time(0, &count, &straight),
+                bar as u64 * 4 * quarter
+            );
+
+            let mut previous = schedule.tick_time(0, &count, &straight);
+            loop {
+                let next = schedule.tick_time(count.tick + 1, &count, &straight);
+                assert!(next > previous);
+                previous = next;
+
+                count.increment(TimeSignature::default(), DEFAULT_PHRASE_LENGTH, next_rate);
+                if count.tick == 0 {
+                    assert_eq!(next, (bar + 1) as u64 * 4 * quarter);
+                    schedule.next_bar(TICKS_PER_BAR);
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -1,19 +1,130 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use anyhow::anyhow;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
 use esp_hal::{
     gpio::{Gpio25, Input},
     time::current_time,
 };
 use fugit::{Instant, MicrosDurationU64};
 
-use super::SHOOT_NOW_SIGNAL;
+use super::{Swing, TimeSignature, DEFAULT_PHRASE_LENGTH, PHRASE_LENGTHS};
 use crate::SHARED;
 
+// wakes up the beat executor on a tap, a downbeat or a newly set tempo
+pub static SHOOT_NOW_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+// number of tap intervals the tempo is taken from
+const TAP_WINDOW: usize = 8;
+const MICROS_PER_MINUTE: f32 = 60_000_000.0;
+
 #[derive(Debug, Clone)]
 pub struct TapInfo {
     pub last_time: Option<Instant<u64, 1, 1000000>>,
     pub interval: Option<u64>,
     pub is_stopped: bool,
-    pub tap_series_count: u8,
-    pub tap_series_start: Option<Instant<u64, 1, 1000000>>,
+    pub recent_intervals: VecDeque<u64>, // the last intervals between taps of a series
+    pub min_bpm: f32,                    // taps slower than this start a new series
+    pub max_bpm: f32,                    // taps faster than this are ignored
+    pub signature: TimeSignature,
+    pub swing: Swing,
+    pub phase_shift: i64, // µs the beat grid still has to be moved by (> 0: later)
+    pub rate: f32,        // pattern speed relative to the tempo, used from the next bar on
+    pub phrase_length: usize,
+}
+
+impl TapInfo {
+    pub fn new() -> Self {
+        Self {
+            last_time: None,
+            interval: None,
+            is_stopped: false,
+            recent_intervals: VecDeque::with_capacity(TAP_WINDOW),
+            min_bpm: 60.0,
+            max_bpm: 300.0,
+            signature: TimeSignature::default(),
+            swing: Swing::default(),
+            phase_shift: 0,
+            rate: 1.0,
+            phrase_length: DEFAULT_PHRASE_LENGTH,
+        }
+    }
+
+    pub fn bpm(&self) -> Option<f32> {
+        self.interval
+            .map(|interval| MICROS_PER_MINUTE / interval as f32)
+    }
+
+    /// Sets the tempo directly instead of tapping it.
+    pub fn set_bpm(&mut self, bpm: f32) -> anyhow::Result<()> {
+        if !(1.0..=1000.0).contains(&bpm) {
+            return Err(anyhow!("BPM {} is not in 1..=1000", bpm));
+        }
+
+        self.interval = Some((MICROS_PER_MINUTE / bpm) as u64);
+        self.recent_intervals.clear();
+
+        Ok(())
+    }
+
+    /// Sets the rate multiplier (0.25..=4.0) applied on top of the tempo.
+    pub fn set_rate(&mut self, rate: f32) -> anyhow::Result<()> {
+        if !(0.25..=4.0).contains(&rate) {
+            return Err(anyhow!("Rate {}x is not in 0.25x..=4x", rate));
+        }
+
+        self.rate = rate;
+
+        Ok(())
+    }
+
+    /// Sets the number of bars per phrase (4, 8, 16 or 32).
+    pub fn set_phrase_length(&mut self, bars: usize) -> anyhow::Result<()> {
+        if !PHRASE_LENGTHS.contains(&bars) {
+            return Err(anyhow!(
+                "Invalid phrase length {}. Use 4, 8, 16 or 32 bars",
+                bars
+            ));
+        }
+
+        self.phrase_length = bars;
+
+        Ok(())
+    }
+
+    /// Changes the tempo range accepted from taps.
+    pub fn set_bpm_range(&mut self, min_bpm: f32, max_bpm: f32) -> anyhow::Result<()> {
+        // comparisons with NaN are always false, so it has to be rejected explicitly
+        if !min_bpm.is_finite() || !max_bpm.is_finite() || min_bpm <= 0.0 || min_bpm >= max_bpm {
+            return Err(anyhow!(
+                "Invalid BPM range {}..{}; needs 0 < min < max",
+                min_bpm,
+                max_bpm
+            ));
+        }
+
+        self.min_bpm = min_bpm;
+        self.max_bpm = max_bpm;
+
+        Ok(())
+    }
+
+    // median of the recent intervals, so that single bad taps don't change the tempo
+    fn median_interval(&self) -> Option<u64> {
+        let mut sorted = self.recent_intervals.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        match sorted.len() {
+            0 => None,
+            n if n % 2 == 1 => Some(sorted[n / 2]),
+            n => Some((sorted[n / 2 - 1] + sorted[n / 2]) / 2),
+        }
+    }
+}
+
+impl Default for TapInfo {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[embassy_executor::task]
@@ -29,20 +140,7 @@ pub fn beat_input() {
     // enter critical section
     critical_section::with(|cs| {
         let mut shared = SHARED.borrow_ref_mut(cs);
-
-        let tap_info = &mut shared.tap_info;
-        if tap_info.is_none() {
-            tap_info.replace(TapInfo {
-                last_time: None,
-                interval: None,
-                is_stopped: false,
-                tap_series_count: 0,
-                tap_series_start: None,
-            });
-        }
-
-        // now, tap_info is definitely Some
-        let tap_info = tap_info.as_mut().unwrap();
+        let tap_info = shared.tap_info.get_or_insert_with(TapInfo::new);
         tap_info.is_stopped = false;
 
         let old_time = tap_info.last_time;
@@ -52,36 +150,33 @@ pub fn beat_input() {
 
         // set last time in info
         tap_info.last_time = Some(current_time);
-        if tap_info.tap_series_start.is_none() {
-            tap_info.tap_series_start = Some(current_time);
-        }
 
         // calc speed and set it
         if let Some(old_t) = old_time {
             let duration = MicrosDurationU64::from_ticks(current_time.ticks() - old_t.ticks());
+            let min_duration = (MICROS_PER_MINUTE / tap_info.max_bpm) as u64;
+            let max_duration = (MICROS_PER_MINUTE / tap_info.min_bpm) as u64;
 
-            if duration.ticks() < 200_000 {
-                // filter out weird triggers (less than 0.2 sec, which would be >300 bpm)
+            if duration.ticks() < min_duration {
+                // filter out weird triggers (faster than the max bpm)
                 log::info!("Ignoring duration: {:?} (too short)", duration);
 
                 // reset to old_time assuming that this was a false positive
                 tap_info.last_time = old_time;
-            } else if duration.ticks() > 1_000_000 {
-                // filter out weird triggers (more than 1 sec, which would be < 60 bpm)
+            } else if duration.ticks() > max_duration {
+                // slower than the min bpm, so this is the first tap of a new series
                 log::info!("Ignoring duration: {:?} (too long)", duration);
-                tap_info.tap_series_start = Some(current_time);
-                tap_info.tap_series_count = 0;
+                tap_info.recent_intervals.clear();
             } else {
                 log::info!("New duration: {:?}", duration);
 
-                tap_info.tap_series_count += 1;
-                let series_duration = MicrosDurationU64::from_ticks(
-                    current_time.ticks() - tap_info.tap_series_start.unwrap().ticks(),
-                );
+                if tap_info.recent_intervals.len() == TAP_WINDOW {
+                    tap_info.recent_intervals.pop_front();
+                }
+                tap_info.recent_intervals.push_back(duration.ticks());
 
                 // set new interval to be used in shoots
-                tap_info.interval =
-                    Some((series_duration / tap_info.tap_series_count as u32).ticks());
+                tap_info.interval = tap_info.median_interval();
             }
         }
     });
@@ -89,3 +184,57 @@ pub fn beat_input() {
     // signal the shooting task to stop waiting
     SHOOT_NOW_SIGNAL.signal(());
 }
+
+/// Sets the tempo to the given BPM. If there was no running beat yet, it starts now.
+pub fn bpm_input(bpm: f32) -> anyhow::Result<()> {
+    let was_running = critical_section::with(|cs| {
+        let mut shared = SHARED.borrow_ref_mut(cs);
+        let tap_info = shared.tap_info.get_or_insert_with(TapInfo::new);
+        let was_running = tap_info.interval.is_some() && !tap_info.is_stopped;
+
+        tap_info.set_bpm(bpm)?;
+        tap_info.is_stopped = false;
+
+        Ok::<_, anyhow::Error>(was_running)
+    })?;
+
+    // a running beat picks up the new interval with its next beat
+    if !was_running {
+        SHOOT_NOW_SIGNAL.signal(());
+    }
+
+    Ok(())
+}
+
+/// Shifts the running beat grid by the given milliseconds (> 0: later, < 0: earlier)
+/// without changing the tempo.
+pub fn nudge_input(ms: f32) -> anyhow::Result<()> {
+    if !(-500.0..=500.0).contains(&ms) {
+        return Err(anyhow!("Nudge of {}ms is not in -500..=500ms", ms));
+    }
+
+    critical_section::with(|cs| {
+        let mut shared = SHARED.borrow_ref_mut(cs);
+        let tap_info = shared
+            .tap_info
+            .as_mut()
+            .filter(|info| info.interval.is_some())
+            .ok_or_else(|| anyhow!("There is no running beat to nudge"))?;
+        tap_info.phase_shift += (ms * 1000.0) as i64;
+
+        Ok(())
+    })
+}
+
+/// Marks the current moment as the first beat of a bar, keeping the tempo.
+pub fn downbeat_input() {
+    critical_section::with(|cs| {
+        let mut shared = SHARED.borrow_ref_mut(cs);
+        if let Some(tap_info) = shared.tap_info.as_mut() {
+            tap_info.is_stopped = false;
+        }
+    });
+
+    // the beat executor restarts the bar when signaled
+    SHOOT_NOW_SIGNAL.signal(());
+}
@@ -0,0 +1,143 @@
+use anyhow::anyhow;
+
+use super::Rgb;
+use crate::MAX_INTENSITY;
+
+/// How a layer is combined with the layers below it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// the layer replaces what is below it
+    #[default]
+    Normal,
+    /// like normal, but the brightness of a pixel is its alpha: black is transparent,
+    /// pixels at `MAX_INTENSITY` or brighter are opaque
+    Alpha,
+    Add,
+    /// the product of both relative to `MAX_INTENSITY`, so that a layer at full
+    /// brightness keeps what is below it
+    Multiply,
+    Screen,
+    Max,
+    Difference,
+}
+
+impl TryFrom<&str> for BlendMode {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "normal" | "n" => Ok(BlendMode::Normal),
+            "alpha" | "a" => Ok(BlendMode::Alpha),
+            "add" => Ok(BlendMode::Add),
+            "multiply" | "mul" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+            "max" => Ok(BlendMode::Max),
+            "difference" | "diff" => Ok(BlendMode::Difference),
+            c => Err(anyhow!("Invalid BlendMode {:?}. Available modes are: normal, alpha, add, multiply, screen, max, difference", c)),
+        }
+    }
+}
+
+impl BlendMode {
+    /// Blends `top` onto `base`, `opacity` (0..=255) fades between the base and the result.
+    pub fn blend(self, base: Rgb, top: Rgb, opacity: u8) -> Rgb {
+        let blended = match self {
+            BlendMode::Normal => top,
+            BlendMode::Alpha => {
+                // patterns rarely get brighter than MAX_INTENSITY, so the alpha is
+                // relative to it instead of to the full 255
+                let alpha = (top.max_channel() as u32 * 255 / MAX_INTENSITY as u32).min(255);
+                return base.mix(&top, (alpha * opacity as u32 / 255) as u8);
+            }
+            BlendMode::Add => Rgb {
+                r: base.r.saturating_add(top.r),
+                g: base.g.saturating_add(top.g),
+                b: base.b.saturating_add(top.b),
+            },
+            BlendMode::Multiply => {
+                per_channel(base, top, |a, b| (a * b / MAX_INTENSITY as u32).min(255))
+            }
+            BlendMode::Screen => per_channel(base, top, |a, b| 255 - (255 - a) * (255 - b) / 255),
+            BlendMode::Max => per_channel(base, top, |a, b| a.max(b)),
+            BlendMode::Difference => per_channel(base, top, |a, b| a.abs_diff(b)),
+        };
+
+        base.mix(&blended, opacity)
+    }
+}
+
+fn per_channel(a: Rgb, b: Rgb, f: impl Fn(u32, u32) -> u32) -> Rgb {
+    Rgb {
+        r: f(a.r as u32, b.r as u32) as u8,
+        g: f(a.g as u32, b.g as u32) as u8,
+        b: f(a.b as u32, b.b as u32) as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alpha_is_relative_to_max_intensity() {
+        let base = Rgb { r: 0, g: 0, b: 20 };
+        let top = Rgb {
+            r: MAX_INTENSITY,
+            g: 0,
+            b: 0,
+        };
+
+        assert_eq!(BlendMode::Alpha.blend(base, top, 255), top);
+        assert_eq!(BlendMode::Alpha.blend(base, Rgb::default(), 255), base);
+
+        let half = BlendMode::Alpha.blend(
+            base,
+            Rgb {
+                r: MAX_INTENSITY / 2,
+                g: 0,
+                b: 0,
+            },
+            255,
+        );
+        assert_eq!(half, Rgb { r: 7, g: 0, b: 10 });
+    }
+
+    #[test]
+    fn multiply_is_relative_to_max_intensity() {
+        let full = Rgb {
+            r: MAX_INTENSITY,
+            g: MAX_INTENSITY,
+            b: MAX_INTENSITY,
+        };
+        let color = Rgb { r: 20, g: 10, b: 0 };
+
+        assert_eq!(BlendMode::Multiply.blend(full, full, 255), full);
+        assert_eq!(BlendMode::Multiply.blend(color, full, 255), color);
+        assert_eq!(
+            BlendMode::Multiply.blend(
+                color,
+                Rgb {
+                    r: 15,
+                    g: 15,
+                    b: 15
+                },
+                255
+            ),
+            Rgb { r: 10, g: 5, b: 0 }
+        );
+        assert_eq!(
+            BlendMode::Multiply
+                .blend(
+                    full,
+                    Rgb {
+                        r: 255,
+                        g: 255,
+                        b: 255
+                    },
+                    255
+                )
+                .r,
+            255
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Hue based color representations.
+//!
+//! Hues are given in degrees (0..360), saturation, value and lightness use the full
+//! range of a `u8` (0..=255) like the channels of `Rgb`.
+
+use super::Rgb;
+
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct Hsv {
+    pub h: u16,
+    pub s: u8,
+    pub v: u8,
+}
+
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct Hsl {
+    pub h: u16,
+    pub s: u8,
+    pub l: u8,
+}
+
+impl Hsv {
+    pub fn new(h: u16, s: u8, v: u8) -> Self {
+        Self { h: h % 360, s, v }
+    }
+
+    /// Rotates the hue by the given degrees, wrapping around at 360.
+    pub fn rotate_hue(&mut self, degrees: i32) {
+        self.h = (self.h as i32 + degrees).rem_euclid(360) as u16;
+    }
+
+    pub fn rotated(&self, degrees: i32) -> Self {
+        let mut copy = *self;
+        copy.rotate_hue(degrees);
+        copy
+    }
+
+    pub fn with_saturation(&self, s: u8) -> Self {
+        Self { s, ..*self }
+    }
+}
+
+impl From<Hsv> for Rgb {
+    fn from(hsv: Hsv) -> Self {
+        let (v, s) = (hsv.v as u32, hsv.s as u32);
+        if s == 0 {
+            return Rgb {
+                r: hsv.v,
+                g: hsv.v,
+                b: hsv.v,
+            };
+        }
+
+        let h = (hsv.h % 360) as u32;
+        // position inside the current 60 degree sector (0..=255)
+        let f = (h % 60) * 255 / 60;
+
+        let p = (v * (255 - s) / 255) as u8;
+        let q = (v * (255 * 255 - s * f) / (255 * 255)) as u8;
+        let t = (v * (255 * 255 - s * (255 - f)) / (255 * 255)) as u8;
+        let v = hsv.v;
+
+        let (r, g, b) = match h / 60 {
+            0 => (v, t, p),
+            1 => (q, v, p),
+            2 => (p, v, t),
+            3 => (p, q, v),
+            4 => (t, p, v),
+            _ => (v, p, q),
+        };
+
+        Rgb { r, g, b }
+    }
+}
+
+impl From<Rgb> for Hsv {
+    fn from(rgb: Rgb) -> Self {
+        let (r, g, b) = (rgb.r as i32, rgb.g as i32, rgb.b as i32);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        if delta == 0 {
+            return Hsv {
+                h: 0,
+                s: 0,
+                v: max as u8,
+            };
+        }
+
+        // rounded division, so that conversions back and forth are stable
+        let div = |a: i32, b: i32| (2 * a + b).div_euclid(2 * b);
+        let h = if max == r {
+            div(60 * (g - b), delta)
+        } else if max == g {
+            120 + div(60 * (b - r), delta)
+        } else {
+            240 + div(60 * (r - g), delta)
+        };
+
+        Hsv {
+            h: h.rem_euclid(360) as u16,
+            s: div(delta * 255, max) as u8,
+            v: max as u8,
+        }
+    }
+}
+
+impl From<Hsl> for Hsv {
+    fn from(hsl: Hsl) -> Self {
+        let (s, l) = (hsl.s as u32, hsl.l as u32);
+        let v = l + s * l.min(255 - l) / 255;
+        let s = (2 * 255 * (v - l)).checked_div(v).unwrap_or(0);
+
+        Hsv {
+            h: hsl.h,
+            s: s.min(255) as u8,
+            v: v as u8,
+        }
+    }
+}
+
+impl From<Hsv> for Hsl {
+    fn from(hsv: Hsv) -> Self {
+        let (s, v) = (hsv.s as u32, hsv.v as u32);
+        let l = v * (2 * 255 - s) / (2 * 255);
+        let s = if l == 0 || l == 255 {
+            0
+        } else {
+            255 * (v - l) / l.min(255 - l)
+        };
+
+        Hsl {
+            h: hsv.h,
+            s: s.min(255) as u8,
+            l: l as u8,
+        }
+    }
+}
+
+impl From<Hsl> for Rgb {
+    fn from(hsl: Hsl) -> Self {
+        Hsv::from(hsl).into()
+    }
+}
+
+impl From<Rgb> for Hsl {
+    fn from(rgb: Rgb) -> Self {
+        Hsv::from(rgb).into()
+    }
+}
@@ -1,6 +1,13 @@
-use core::num::ParseIntError;
+use anyhow::anyhow;
 
-use esp_hal::rng::Rng;
+use crate::util::random::RandomSource;
+use hsv::Hsv;
+
+pub mod blend;
+pub mod hsv;
+pub mod oklab;
+pub mod palette;
+pub mod random;
 
 #[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
 pub struct Rgb {
@@ -10,13 +17,13 @@ pub struct Rgb {
 }
 
 impl Rgb {
-    pub fn random(rng: &mut Rng, max_intensity: u8) -> Self {
+    pub fn random(rng: &mut dyn RandomSource, max_intensity: u8) -> Self {
         let mut res = Self::default();
         res.fill_random(rng, max_intensity);
         res
     }
 
-    pub fn fill_random(&mut self, rng: &mut Rng, max_intensity: u8) {
+    pub fn fill_random(&mut self, rng: &mut dyn RandomSource, max_intensity: u8) {
         self.r = rng.random() as u8 % max_intensity;
         self.g = rng.random() as u8 % max_intensity;
         self.b = rng.random() as u8 % max_intensity;
@@ -29,38 +36,255 @@ impl Rgb {
     }
 
     pub fn scaled(&self, scale: u8) -> Rgb {
-        let mut copy = self.clone();
+        let mut copy = *self;
         copy.scale(scale);
         copy
     }
 
+    /// Returns the color scaled so that a full channel (255) becomes `max_intensity`.
+    pub fn limited(&self, max_intensity: u8) -> Rgb {
+        let limit = |c: u8| ((c as u32 * max_intensity as u32) / 255) as u8;
+
+        Rgb {
+            r: limit(self.r),
+            g: limit(self.g),
+            b: limit(self.b),
+        }
+    }
+
+    /// Fades towards `other` by `amount` (0: self, 255: other).
+    pub fn mix(&self, other: &Rgb, amount: u8) -> Rgb {
+        let t = amount as u32;
+        let mix = |a: u8, b: u8| ((a as u32 * (255 - t) + b as u32 * t + 127) / 255) as u8;
+
+        Rgb {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+        }
+    }
+
+    /// Returns the value of the brightest channel.
+    pub fn max_channel(&self) -> u8 {
+        self.r.max(self.g).max(self.b)
+    }
+
     pub fn add(&mut self, rhs: &Rgb) {
         self.r = self.r.saturating_add(rhs.r);
         self.g = self.g.saturating_add(rhs.g);
         self.b = self.b.saturating_add(rhs.b);
     }
 
-    pub fn from(hex_str: &str) -> Result<Self, ParseIntError> {
-        if hex_str.len() != 6 {
-            return Ok(Self::default());
+    /// Parses a hex color with either 6 ("rrggbb") or 3 ("rgb") digits.
+    pub fn from(hex_str: &str) -> anyhow::Result<Self> {
+        if !hex_str.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!("Invalid hex digits in color {:?}", hex_str));
         }
 
-        let r = u8::from_str_radix(&hex_str[0..2], 16)?;
-        let g = u8::from_str_radix(&hex_str[2..4], 16)?;
-        let b = u8::from_str_radix(&hex_str[4..6], 16)?;
+        let channel = |range: core::ops::Range<usize>| {
+            u8::from_str_radix(&hex_str[range], 16).map_err(|_| anyhow!("Invalid hex color"))
+        };
 
-        Ok(Self { r, g, b })
+        match hex_str.len() {
+            6 => Ok(Self {
+                r: channel(0..2)?,
+                g: channel(2..4)?,
+                b: channel(4..6)?,
+            }),
+            // short form, every digit is doubled ("f80" -> "ff8800")
+            3 => Ok(Self {
+                r: channel(0..1)? * 17,
+                g: channel(1..2)? * 17,
+                b: channel(2..3)? * 17,
+            }),
+            len => Err(anyhow!(
+                "Hex color {:?} has {} digits, expected 3 or 6",
+                hex_str,
+                len
+            )),
+        }
     }
 
-    pub fn random_with_variation(base_color: &Self, variation: &Self, rng: &mut Rng) -> Self {
+    /// Looks up a color by its name (e.g. "red" or "warmwhite").
+    pub fn named(name: &str) -> Option<Self> {
+        NAMED_COLORS
+            .iter()
+            .find(|(color_name, _)| name.eq_ignore_ascii_case(color_name))
+            .map(|(_, hex)| Self::from(hex).unwrap())
+    }
+
+    /// Approximates the color of a black body with the given temperature in Kelvin.
+    pub fn from_kelvin(kelvin: u32) -> anyhow::Result<Self> {
+        if !KELVIN_RANGE.contains(&kelvin) {
+            return Err(anyhow!(
+                "Color temperature {}K out of range ({}K to {}K)",
+                kelvin,
+                KELVIN_RANGE.start(),
+                KELVIN_RANGE.end()
+            ));
+        }
+
+        // curve fit by Tanner Helland, works on the temperature in hundreds of Kelvin
+        let temp = kelvin as f32 / 100.0;
+        let clamp = |c: f32| c.clamp(0.0, 255.0) as u8;
+
+        let (r, g) = if temp <= 66.0 {
+            (255.0, 99.470_8 * libm::logf(temp) - 161.119_57)
+        } else {
+            (
+                329.698_73 * libm::powf(temp - 60.0, -0.133_204_76),
+                288.122_17 * libm::powf(temp - 60.0, -0.075_514_85),
+            )
+        };
+        let b = if temp >= 66.0 {
+            255.0
+        } else if temp <= 19.0 {
+            0.0
+        } else {
+            138.517_73 * libm::logf(temp - 10.0) - 305.044_8
+        };
+
+        Ok(Self {
+            r: clamp(r),
+            g: clamp(g),
+            b: clamp(b),
+        })
+    }
+
+    /// Splits off the white part of the color, so that RGBW strips can show it on their
+    /// dedicated white LED instead of mixing it from the three color LEDs.
+    ///
+    /// `white` is the color of the white LED at full brightness (e.g. a warm white for
+    /// 3000K LEDs), only the part of the color matching it is moved to the white LED.
+    pub fn to_rgbw(&self, white: &Rgb) -> Rgbw {
+        if *white == Rgb::default() {
+            return Rgbw {
+                r: self.r,
+                g: self.g,
+                b: self.b,
+                w: 0,
+            };
+        }
+
+        // how bright the white LED can be before one of the channels would be exceeded
+        let share = |c: u8, white_c: u8| match white_c {
+            0 => 255,
+            _ => (c as u32 * 255 / white_c as u32).min(255),
+        };
+        let w = share(self.r, white.r)
+            .min(share(self.g, white.g))
+            .min(share(self.b, white.b));
+        let rest = |c: u8, white_c: u8| c - (w * white_c as u32 / 255) as u8;
+
+        Rgbw {
+            r: rest(self.r, white.r),
+            g: rest(self.g, white.g),
+            b: rest(self.b, white.b),
+            w: w as u8,
+        }
+    }
+
+    /// Rotates the hue of the color by the given degrees.
+    pub fn rotate_hue(&mut self, degrees: i32) {
+        *self = Hsv::from(*self).rotated(degrees).into();
+    }
+
+    /// Sets the saturation of the color (0: grey, 255: fully saturated).
+    pub fn set_saturation(&mut self, saturation: u8) {
+        *self = Hsv::from(*self).with_saturation(saturation).into();
+    }
+
+    pub fn random_with_variation(
+        base_color: &Self,
+        variation: &Self,
+        rng: &mut dyn RandomSource,
+    ) -> Self {
         let r_var = ((rng.random() % 100) as i32 - 50) * variation.r as i32 / 50;
         let g_var = ((rng.random() % 100) as i32 - 50) * variation.g as i32 / 50;
         let b_var = ((rng.random() % 100) as i32 - 50) * variation.b as i32 / 50;
 
         Self {
-            r: (base_color.r as i32 + r_var).max(0).min(255) as u8,
-            g: (base_color.g as i32 + g_var).max(0).min(255) as u8,
-            b: (base_color.b as i32 + b_var).max(0).min(255) as u8,
+            r: (base_color.r as i32 + r_var).clamp(0, 255) as u8,
+            g: (base_color.g as i32 + g_var).clamp(0, 255) as u8,
+            b: (base_color.b as i32 + b_var).clamp(0, 255) as u8,
+        }
+    }
+}
+
+/// Color with 8 additional fractional bits per channel, used by patterns that fade too
+/// slowly for whole steps and between the stages of the render pipeline.
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct Rgb16 {
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
+}
+
+impl Rgb16 {
+    /// Scales the color by `scale` (1.0: unchanged), keeping the fractions.
+    pub fn scaled(rgb: &Rgb, scale: f32) -> Self {
+        let channel = |c: u8| (c as f32 * scale * 256.0).clamp(0.0, u16::MAX as f32) as u16;
+
+        Self {
+            r: channel(rgb.r),
+            g: channel(rgb.g),
+            b: channel(rgb.b),
+        }
+    }
+
+    /// Rounds to the nearest color without fractions.
+    pub fn rounded(&self) -> Rgb {
+        let round = |c: u16| ((c as u32 + 0x80) >> 8).min(255) as u8;
+
+        Rgb {
+            r: round(self.r),
+            g: round(self.g),
+            b: round(self.b),
+        }
+    }
+}
+
+impl From<Rgb> for Rgb16 {
+    fn from(rgb: Rgb) -> Self {
+        Self {
+            r: (rgb.r as u16) << 8,
+            g: (rgb.g as u16) << 8,
+            b: (rgb.b as u16) << 8,
         }
     }
 }
+
+pub const WHITE: Rgb = Rgb {
+    r: 255,
+    g: 255,
+    b: 255,
+};
+
+pub const KELVIN_RANGE: core::ops::RangeInclusive<u32> = 1000..=40000;
+
+pub const NAMED_COLORS: [(&str, &str); 16] = [
+    ("black", "000000"),
+    ("off", "000000"),
+    ("white", "ffffff"),
+    ("red", "ff0000"),
+    ("green", "00ff00"),
+    ("blue", "0000ff"),
+    ("yellow", "ffff00"),
+    ("cyan", "00ffff"),
+    ("magenta", "ff00ff"),
+    ("orange", "ff8000"),
+    ("purple", "8000ff"),
+    ("pink", "ff4080"),
+    ("warmwhite", "ffa757"),
+    ("neutralwhite", "ffcda6"),
+    ("coldwhite", "fff9fd"),
+    ("amber", "ffbf00"),
+];
+
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct Rgbw {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub w: u8,
+}
@@ -0,0 +1,125 @@
+use alloc::vec::Vec;
+
+use super::Rgb;
+
+/// A color in the Oklab color space, in which the distances between colors match the
+/// perceived differences. Interpolating here avoids the grey and dark spots in between
+/// two colors that a plain RGB interpolation produces.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Oklab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl Oklab {
+    /// Interpolates towards `other` (t: 0.0 -> self, 1.0 -> other).
+    pub fn lerp(&self, other: &Oklab, t: f32) -> Oklab {
+        Oklab {
+            l: self.l + (other.l - self.l) * t,
+            a: self.a + (other.a - self.a) * t,
+            b: self.b + (other.b - self.b) * t,
+        }
+    }
+}
+
+/// sRGB channel (0..=255) to linear light (0.0..=1.0)
+fn to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        libm::powf((c + 0.055) / 1.055, 2.4)
+    }
+}
+
+/// linear light (0.0..=1.0) to sRGB channel (0..=255)
+fn from_linear(c: f32) -> u8 {
+    let c = if c <= 0.003_130_8 {
+        12.92 * c
+    } else {
+        1.055 * libm::powf(c, 1.0 / 2.4) - 0.055
+    };
+
+    (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8
+}
+
+// conversion matrices from https://bottosson.github.io/posts/oklab/
+impl From<Rgb> for Oklab {
+    fn from(rgb: Rgb) -> Self {
+        let (r, g, b) = (to_linear(rgb.r), to_linear(rgb.g), to_linear(rgb.b));
+
+        let l = libm::cbrtf(0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_99 * b);
+        let m = libm::cbrtf(0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b);
+        let s = libm::cbrtf(0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b);
+
+        Self {
+            l: 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            a: 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            b: 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+        }
+    }
+}
+
+impl From<Oklab> for Rgb {
+    fn from(lab: Oklab) -> Self {
+        let l = lab.l + 0.396_337_78 * lab.a + 0.215_803_76 * lab.b;
+        let m = lab.l - 0.105_561_346 * lab.a - 0.063_854_17 * lab.b;
+        let s = lab.l - 0.089_484_18 * lab.a - 1.291_485_5 * lab.b;
+        let (l, m, s) = (l * l * l, m * m * m, s * s * s);
+
+        Self {
+            r: from_linear(4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s),
+            g: from_linear(-1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s),
+            b: from_linear(-0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s),
+        }
+    }
+}
+
+/// Colors placed at positions between 0.0 and 1.0, sampled with Oklab interpolation.
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    stops: Vec<(f32, Oklab)>,
+}
+
+impl Gradient {
+    /// Creates a gradient from (position, color) stops, the positions are sorted.
+    pub fn new(stops: impl IntoIterator<Item = (f32, Rgb)>) -> Self {
+        let mut stops = stops
+            .into_iter()
+            .map(|(pos, rgb)| (pos.clamp(0.0, 1.0), Oklab::from(rgb)))
+            .collect::<Vec<_>>();
+        assert!(!stops.is_empty());
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Self { stops }
+    }
+
+    /// Creates a gradient with the colors evenly spread from 0.0 to 1.0.
+    pub fn even(colors: &[Rgb]) -> Self {
+        let last = (colors.len() - 1).max(1) as f32;
+        Self::new(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, rgb)| (i as f32 / last, *rgb)),
+        )
+    }
+
+    /// Returns the color at the position, positions outside of the stops get the
+    /// nearest stop's color.
+    pub fn sample(&self, position: f32) -> Rgb {
+        let next = self.stops.iter().position(|(pos, _)| *pos > position);
+
+        let lab = match next {
+            Some(0) => self.stops[0].1,
+            None => self.stops[self.stops.len() - 1].1,
+            Some(i) => {
+                let ((pos_a, a), (pos_b, b)) = (self.stops[i - 1], self.stops[i]);
+                a.lerp(&b, (position - pos_a) / (pos_b - pos_a))
+            }
+        };
+
+        lab.into()
+    }
+}
@@ -0,0 +1,113 @@
+use alloc::vec::Vec;
+use anyhow::anyhow;
+
+use super::{oklab::Gradient, Rgb};
+
+/// A set of colors that patterns can use instead of random colors.
+///
+/// The colors can either be cycled through with `next()` or sampled at any position of
+/// the palette, which interpolates between neighbouring colors in the Oklab color space.
+/// The palette wraps around, so that position 255 is close to the first color again.
+#[derive(Clone, Debug)]
+pub struct Palette {
+    colors: Vec<Rgb>,
+    gradient: Gradient, // the colors, followed by the first one again for the wrap around
+    index: usize,
+}
+
+static BUILTIN_PALETTES: [(&str, &[u32]); 7] = [
+    (
+        "rainbow",
+        &[0xff0000, 0xffff00, 0x00ff00, 0x00ffff, 0x0000ff, 0xff00ff],
+    ),
+    ("fire", &[0xff2000, 0xff6000, 0xffa000, 0xff0000]),
+    ("ocean", &[0x0020ff, 0x00a0ff, 0x00ffc0, 0x0040a0]),
+    ("forest", &[0x00ff20, 0x60ff00, 0x208000, 0xa0ff40]),
+    ("sunset", &[0xff4000, 0xff0060, 0x8000ff, 0xffa000]),
+    ("ice", &[0xffffff, 0x80c0ff, 0x2060ff, 0xc0ffff]),
+    ("party", &[0xff00a0, 0x00ffff, 0xffff00, 0x8000ff]),
+];
+
+impl Palette {
+    pub fn new(colors: Vec<Rgb>) -> Self {
+        assert!(!colors.is_empty());
+
+        let gradient = Gradient::even(&[&colors[..], &colors[..1]].concat());
+
+        Self {
+            colors,
+            gradient,
+            index: 0,
+        }
+    }
+
+    pub fn primary(&self) -> Rgb {
+        self.colors[0]
+    }
+
+    pub fn secondary(&self) -> Rgb {
+        self.colors[1 % self.colors.len()]
+    }
+
+    pub fn tertiary(&self) -> Rgb {
+        self.colors[2 % self.colors.len()]
+    }
+
+    /// Returns the color at the given position (0..=255 for the whole palette),
+    /// interpolated between the two closest colors.
+    pub fn sample(&self, position: u8) -> Rgb {
+        self.gradient.sample(position as f32 / 256.0)
+    }
+
+    /// Cycles through the colors from the first one again.
+    pub fn rewind(&mut self) {
+        self.index = 0;
+    }
+}
+
+impl TryFrom<&str> for Palette {
+    type Error = anyhow::Error;
+
+    /// Creates a palette either by the name of a built-in palette (e.g. "fire") or from
+    /// a list of hex colors separated by '-' (e.g. "ff0000-00ff00-0000ff").
+    fn try_from(palette: &str) -> Result<Self, Self::Error> {
+        if let Some((_, colors)) = BUILTIN_PALETTES.iter().find(|(name, _)| *name == palette) {
+            return Ok(Self::new(
+                colors
+                    .iter()
+                    .map(|c| Rgb {
+                        r: (c >> 16) as u8,
+                        g: (c >> 8) as u8,
+                        b: *c as u8,
+                    })
+                    .collect(),
+            ));
+        }
+
+        let colors = palette
+            .split('-')
+            .map(Rgb::from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| {
+                anyhow!(
+                    "Invalid palette {:?}. Use a list of hex colors (rrggbb-rrggbb-...) or one of: {}",
+                    palette,
+                    BUILTIN_PALETTES.map(|(name, _)| name).join(", ")
+                )
+            })?;
+
+        Ok(Self::new(colors))
+    }
+}
+
+impl Iterator for Palette {
+    type Item = Rgb;
+
+    // cycles through the colors endlessly
+    fn next(&mut self) -> Option<Rgb> {
+        let color = self.colors[self.index];
+        self.index = (self.index + 1) % self.colors.len();
+
+        Some(color)
+    }
+}
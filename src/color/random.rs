@@ -0,0 +1,116 @@
+//! Strategies for picking random colors.
+//!
+//! Picking each channel randomly mostly gives greyish colors, the hue based strategies
+//! always give saturated colors that fit together.
+
+use anyhow::anyhow;
+
+use super::{hsv::Hsv, Rgb};
+use crate::{patterns::command::parse_rgb, util::random::RandomSource};
+
+// the golden angle (137.5 degrees) in 1/16 degrees; stepping the hue by it keeps
+// consecutive colors far apart, the hues only repeat after 144 steps (2200/5760 = 55/144)
+const GOLDEN_ANGLE: u16 = 2200;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub enum ColorStrategy {
+    /// every channel random (greyish colors)
+    #[default]
+    Channels,
+    /// random hue with a fixed saturation
+    Hue,
+    /// the hue steps by the golden angle
+    Golden,
+    /// the base color or its complement
+    Complementary(Hsv),
+    /// hues up to 30 degrees next to the base color
+    Analogous(Hsv),
+    /// the base color or one of the two colors 120 degrees apart
+    Triadic(Hsv),
+}
+
+#[derive(Clone, Debug)]
+pub struct RandomColors {
+    strategy: ColorStrategy,
+    saturation: u8,
+    hue: u16, // last hue of the golden angle steps in 1/16 degrees
+}
+
+impl Default for RandomColors {
+    fn default() -> Self {
+        Self {
+            strategy: ColorStrategy::default(),
+            saturation: 255,
+            hue: 0,
+        }
+    }
+}
+
+impl TryFrom<&str> for RandomColors {
+    type Error = anyhow::Error;
+
+    /// Parses the strategy as `<name>[ <arg>]`:
+    /// rgb; hue [saturation %]; golden [saturation %]; comp <color>; analog <color>;
+    /// triad <color>
+    fn try_from(args: &str) -> Result<Self, Self::Error> {
+        let (name, arg) = args.split_once(' ').unwrap_or((args, ""));
+
+        let saturation = || -> anyhow::Result<u8> {
+            if arg.is_empty() {
+                return Ok(255);
+            }
+            match arg.parse::<u8>() {
+                Ok(percent) if percent <= 100 => Ok((percent as u32 * 255 / 100) as u8),
+                _ => Err(anyhow!("Saturation {:?} is not in 0..=100%", arg)),
+            }
+        };
+        let base = || parse_rgb(arg).map(Hsv::from);
+
+        let (strategy, saturation) = match name {
+            "rgb" => (ColorStrategy::Channels, 255),
+            "hue" => (ColorStrategy::Hue, saturation()?),
+            "golden" => (ColorStrategy::Golden, saturation()?),
+            "comp" => (ColorStrategy::Complementary(base()?), 255),
+            "analog" => (ColorStrategy::Analogous(base()?), 255),
+            "triad" => (ColorStrategy::Triadic(base()?), 255),
+            s => {
+                return Err(anyhow!(
+                    "Invalid color strategy {:?}. Use rgb, hue [s], golden [s], comp <color>, analog <color> or triad <color>",
+                    s
+                ))
+            }
+        };
+
+        Ok(Self {
+            strategy,
+            saturation,
+            hue: 0,
+        })
+    }
+}
+
+impl RandomColors {
+    /// Starts the golden angle steps from the first hue again.
+    pub fn rewind(&mut self) {
+        self.hue = 0;
+    }
+
+    /// Returns the next color, its brightest channel is at most `max_intensity`.
+    pub fn next(&mut self, rng: &mut dyn RandomSource, max_intensity: u8) -> Rgb {
+        let hsv = match self.strategy {
+            ColorStrategy::Channels => return Rgb::random(rng, max_intensity),
+            ColorStrategy::Hue => Hsv::new((rng.random() % 360) as u16, self.saturation, 255),
+            ColorStrategy::Golden => {
+                self.hue = (self.hue + GOLDEN_ANGLE) % (360 * 16);
+                Hsv::new(self.hue / 16, self.saturation, 255)
+            }
+            ColorStrategy::Complementary(base) => base.rotated(180 * (rng.random() % 2) as i32),
+            ColorStrategy::Analogous(base) => base.rotated((rng.random() % 61) as i32 - 30),
+            ColorStrategy::Triadic(base) => base.rotated(120 * (rng.random() % 3) as i32),
+        };
+
+        // the relative strategies keep the saturation of the base color at full brightness
+        let rgb: Rgb = Hsv { v: 255, ..hsv }.into();
+        rgb.limited(max_intensity)
+    }
+}
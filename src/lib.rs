@@ -0,0 +1,22 @@
+//! Everything that doesn't touch the hardware: colors, patterns, the beat math and the
+//! bit encoding of the LED protocols. It is shared with the ESP32 binary and can be
+//! tested on any host with `cargo +stable test --target <host triple>`.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod beat;
+pub mod color;
+pub mod patterns;
+
+pub mod transmit {
+    pub mod encoding;
+}
+
+pub mod util {
+    pub mod random;
+}
+
+pub const MAX_INTENSITY: u8 = 30;
+pub const RENDERS_PER_SECOND: usize = 50;
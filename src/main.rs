@@ -3,12 +3,19 @@
 
 extern crate alloc;
 
-mod beat;
-mod color;
-mod patterns;
+// the hardware-free modules of the lib, together with the tasks driving them
+mod beat {
+    pub use yalbir::beat::*;
+
+    pub mod counting;
+    pub mod tapping;
+}
+mod pipeline;
 mod transmit;
 mod util;
 
+use yalbir::{color, patterns, MAX_INTENSITY, RENDERS_PER_SECOND};
+
 use alloc::boxed::Box;
 use core::{cell::RefCell, mem::MaybeUninit};
 
@@ -17,17 +24,19 @@ use critical_section::Mutex;
 use embassy_executor::Spawner;
 use embassy_time::Timer;
 use esp_backtrace as _;
+#[cfg(not(feature = "apa102"))]
+use esp_hal::rmt::TxChannelCreator;
+#[cfg(feature = "apa102")]
+use esp_hal::spi::{master::Spi, SpiMode};
 use esp_hal::{
     clock::ClockControl,
     gpio::{Gpio26, Input, Io, Level, Output, Pull},
     peripherals::Peripherals,
     prelude::*,
-    rmt::Channel,
     rng::Rng,
     system::SystemControl,
     time::current_time,
     timer::timg::TimerGroup,
-    Blocking,
 };
 use esp_wifi::{ble::controller::asynch::BleConnector, initialize, EspWifiInitFor};
 
@@ -43,25 +52,42 @@ use patterns::{
     strobe::{Strobe, StrobeMode},
     LedPattern,
 };
-use transmit::send_data;
-use util::ble::ble_handling;
+use pipeline::Pipeline;
+#[cfg(feature = "apa102")]
+use transmit::spi::Apa102Output;
+use transmit::Outputs;
+#[cfg(not(feature = "apa102"))]
+use transmit::{rmt::RmtOutput, ColorOrder, LedChip, StripConfig};
+use util::{
+    ble::ble_handling,
+    random::{get_rng, set_hardware_rng, RandomSource},
+};
 
+// initial strip length, can be changed at runtime via the "leds" command
 const N_LEDS: usize = 44 + 11 + 12;
-const MAX_INTENSITY: u8 = 30;
-const RENDERS_PER_SECOND: usize = 50;
 const RENDER_INTERVAL: usize = 1000 / RENDERS_PER_SECOND; // in milliseconds
-const HEAP_SIZE: usize = 32 * 1024;
+const HEAP_SIZE: usize = 80 * 1024;
+// heap needed per LED of the frame: the partition (3 bytes), the pipeline's buffers
+// (6 + 3), mapping (3 + 8), power estimation (2 + 2), dithering (3) and the patterns' own
+// buffers (at least 6)
+const BYTES_PER_LED: usize = 36;
+// the 32 KiB the controller used to run with are kept for everything but the LEDs
+const MAX_LEDS: usize = (HEAP_SIZE - 32 * 1024) / BYTES_PER_LED;
 
 struct SharedItems<'a> {
     tap_info: Option<TapInfo>,
     led: Option<Output<'a, Gpio26>>,
     rgbs: Option<PartitionedPatterns>,
+    outputs: Option<Outputs>,
+    pipeline: Option<Pipeline>,
 }
 
 static SHARED: Mutex<RefCell<SharedItems>> = Mutex::new(RefCell::new(SharedItems {
     tap_info: None,
     led: None,
     rgbs: None,
+    outputs: None,
+    pipeline: None,
 }));
 static RNG: Mutex<RefCell<Option<Rng>>> = Mutex::new(RefCell::new(None));
 
@@ -85,9 +111,11 @@ async fn main(spawner: Spawner) {
     let io = Io::new(peripherals.GPIO, peripherals.IO_MUX);
 
     let rng = Rng::new(peripherals.RNG);
+    critical_section::with(|cs| RNG.borrow_ref_mut(cs).replace(rng));
+    set_hardware_rng(hardware_rng);
 
     let led = Output::new(io.pins.gpio26, Level::Low);
-    let rgbs = init_rgbs(rng);
+    let rgbs = init_rgbs();
 
     critical_section::with(|cs| {
         let mut shared = SHARED.borrow_ref_mut(cs);
@@ -95,15 +123,62 @@ async fn main(spawner: Spawner) {
         shared.rgbs.replace(rgbs);
     });
 
-    critical_section::with(|cs| RNG.borrow_ref_mut(cs).replace(rng));
-
     // create the task that listens to the beat button being pressed
     let button = Input::new(io.pins.gpio25, Pull::Up);
     spawner.spawn(button_press_handler(button)).ok();
 
-    // create the RGB LED strip render task giving it the RMT channel
-    let channel = transmit::init_rmt(peripherals.RMT, io.pins.gpio27, &clocks);
-    spawner.spawn(render(channel)).ok();
+    // configure the outputs the LED strips are connected to and create the render task
+    let mut outputs = Outputs::new();
+    #[cfg(not(feature = "apa102"))]
+    {
+        // one strip per RMT channel on GPIO27, GPIO32, GPIO33 and GPIO14; each of them
+        // shows the whole frame until it is given its own part with the "range" command
+        let rmt = transmit::init_rmt(peripherals.RMT, &clocks);
+        let config = StripConfig::new(ColorOrder::Grb, LedChip::Ws2812b);
+        let tx_config = transmit::tx_channel_config();
+        outputs.add(
+            Box::new(RmtOutput::new(
+                rmt.channel0.configure(io.pins.gpio27, tx_config).unwrap(),
+                config,
+            )),
+            (0, N_LEDS),
+        );
+        outputs.add(
+            Box::new(RmtOutput::new(
+                rmt.channel1.configure(io.pins.gpio32, tx_config).unwrap(),
+                config,
+            )),
+            (0, N_LEDS),
+        );
+        outputs.add(
+            Box::new(RmtOutput::new(
+                rmt.channel2.configure(io.pins.gpio33, tx_config).unwrap(),
+                config,
+            )),
+            (0, N_LEDS),
+        );
+        outputs.add(
+            Box::new(RmtOutput::new(
+                rmt.channel3.configure(io.pins.gpio14, tx_config).unwrap(),
+                config,
+            )),
+            (0, N_LEDS),
+        );
+    }
+    #[cfg(feature = "apa102")]
+    {
+        // clock on GPIO18, data on GPIO23
+        let spi = Spi::new(peripherals.SPI2, 8.MHz(), SpiMode::Mode0, &clocks)
+            .with_sck(io.pins.gpio18)
+            .with_mosi(io.pins.gpio23);
+        outputs.add(Box::new(Apa102Output::new(spi)), (0, N_LEDS));
+    }
+    critical_section::with(|cs| {
+        let mut shared = SHARED.borrow_ref_mut(cs);
+        shared.outputs.replace(outputs);
+        shared.pipeline.replace(Pipeline::new());
+    });
+    spawner.spawn(render()).ok();
 
     // create the task that fires in intervals according to the music's beat
     spawner.spawn(beat_executor()).ok();
@@ -135,44 +210,62 @@ fn init_heap() {
     }
 }
 
-fn init_rgbs(rng: Rng) -> PartitionedPatterns {
+// the patterns' random numbers come from the hardware RNG unless a seed is set
+struct HardwareRng(Rng);
+
+impl RandomSource for HardwareRng {
+    fn random(&mut self) -> u32 {
+        self.0.random()
+    }
+}
+
+fn hardware_rng() -> Box<dyn RandomSource> {
+    critical_section::with(|cs| Box::new(HardwareRng(RNG.borrow_ref(cs).unwrap())))
+}
+
+fn init_rgbs() -> PartitionedPatterns {
     let mut rgbs = PartitionedPatterns::new(N_LEDS);
-    rgbs.add(Box::new(CaterPillars::new(44, None, 120, rng)), None);
+    rgbs.add(Box::new(CaterPillars::new(44, None, 120, get_rng())), None);
     rgbs.add(
         Box::new(Breathing::new(
             10,
             patterns::breathing::BreathingMode::Mixed,
             60,
-            rng,
+            get_rng(),
             0.5,
         )),
         None,
     );
-    rgbs.add(Box::new(Strobe::new(12, StrobeMode::Unison, rng, 6)), None);
+    rgbs.add(
+        Box::new(Strobe::new(12, StrobeMode::Unison, get_rng(), 6)),
+        None,
+    );
 
     rgbs
 }
 
 #[embassy_executor::task]
-async fn render(rmt_channel: Channel<Blocking, 0>) -> ! {
-    let channel: Mutex<RefCell<Option<Channel<Blocking, 0>>>> =
-        Mutex::new(RefCell::new(Some(rmt_channel)));
-
+async fn render() -> ! {
     loop {
         let process_start_time = current_time();
 
         critical_section::with(|cs| {
             // wait clears the interrupt
             let mut shared = SHARED.borrow_ref_mut(cs);
+            let shared = &mut *shared;
 
             let rgb_data = shared.rgbs.as_mut().unwrap();
+            let outputs = shared.outputs.as_mut().unwrap();
+            let frame = shared
+                .pipeline
+                .as_mut()
+                .unwrap()
+                .process(rgb_data, outputs.ranges());
 
             // ATTENTION: apparently this operation cant simply be moved out of the
             // closure as a side effect is, that the sending is somehow interrupted
             // from time to time leading to weird jittering in the animation.
-            let mut ch = channel.borrow_ref_mut(cs);
-            let c = send_data(rgb_data.next(), ch.take().unwrap());
-            ch.replace(c);
+            outputs.send(frame);
         });
 
         // wait less millis accounting for how long the previous render took
@@ -2,10 +2,13 @@ use alloc::{boxed::Box, vec, vec::Vec};
 use anyhow::anyhow;
 use nom::bytes::complete::tag;
 
-use crate::{beat::BeatCount, color::Rgb};
+use crate::{
+    beat::BeatCount,
+    color::{palette::Palette, Rgb},
+};
 
 use super::{
-    command::hex_rgb, pattern_with_args_from_command, LedPattern, PatternCommand, PatternKind,
+    command::parse_rgb, pattern_with_args_from_command, LedPattern, PatternCommand, PatternKind,
 };
 
 pub struct Background {
@@ -45,6 +48,16 @@ impl LedPattern for Background {
         self.rgbs.len()
     }
 
+    // the background gets the tertiary color, keeping its brightness
+    fn set_palette(&mut self, palette: &Palette) {
+        self.color = palette.tertiary().limited(self.color.max_channel());
+        self.pattern.set_palette(palette);
+    }
+
+    fn reseed(&mut self) {
+        self.pattern.reseed();
+    }
+
     fn from_str(args: &str) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -62,18 +75,7 @@ impl LedPattern for Background {
                 anyhow!("Missing comma after background pattern args")
             })?;
 
-        let (_, background) =
-            hex_rgb(remainder).map_err(|err: nom::Err<nom::error::Error<&str>>| {
-                if let nom::Err::Error(error) = err {
-                    if let nom::error::ErrorKind::Tag = error.code {
-                        anyhow!("Invalid hex representation (Missing '#' sign?)")
-                    } else {
-                        anyhow!("Invalid hex numbers")
-                    }
-                } else {
-                    anyhow!("Unknown error while parsing hex: {:?}", err)
-                }
-            })?;
+        let background = parse_rgb(remainder)?;
 
         Ok(Self::new(pattern, background))
     }
@@ -1,10 +1,13 @@
 use super::{LedPattern, PatternCommand};
 use crate::{
-    beat::BeatCount, color::Rgb, patterns::invalid_cmd, util::random::get_rng, RENDERS_PER_SECOND,
+    beat::BeatCount,
+    color::{palette::Palette, random::RandomColors, Rgb, Rgb16},
+    patterns::{command, invalid_cmd},
+    util::random::{get_rng, RandomSource},
+    RENDERS_PER_SECOND,
 };
-use alloc::{vec, vec::Vec};
+use alloc::{boxed::Box, vec, vec::Vec};
 use anyhow::anyhow;
-use esp_hal::rng::Rng;
 use nom::{
     branch::alt,
     bytes::complete::tag,
@@ -23,7 +26,9 @@ pub struct Breathing {
     speed: f32,
     max_intensity: u8,
     mode: BreathingMode,
-    rng: Rng,
+    rng: Box<dyn RandomSource>,
+    palette: Option<Palette>,
+    colors: RandomColors, // used if there is no palette
 }
 
 #[derive(Clone)]
@@ -45,7 +50,7 @@ impl Breathing {
         n_leds: usize,
         mode: BreathingMode,
         max_intensity: u8,
-        rng: Rng,
+        rng: Box<dyn RandomSource>,
         speed: f32,
     ) -> Self {
         let mut res = Self {
@@ -57,6 +62,8 @@ impl Breathing {
             max_intensity,
             mode,
             rng,
+            palette: None,
+            colors: RandomColors::default(),
         };
 
         res.switch_colors();
@@ -67,16 +74,23 @@ impl Breathing {
 
 impl Breathing {
     fn switch_colors(&mut self) {
-        self.rgbs_max
-            .iter_mut()
-            .for_each(|col| col.fill_random(&mut self.rng, self.max_intensity));
+        if let Some(palette) = self.palette.as_ref() {
+            for col in self.rgbs_max.iter_mut() {
+                *col = palette
+                    .sample(self.rng.random() as u8)
+                    .limited(self.max_intensity);
+            }
+        } else {
+            for col in self.rgbs_max.iter_mut() {
+                *col = self.colors.next(self.rng.as_mut(), self.max_intensity);
+            }
+        }
 
         self.rgbs_current.copy_from_slice(&self.rgbs_max[..]);
     }
-}
 
-impl LedPattern for Breathing {
-    fn next(&mut self) -> &[Rgb] {
+    // moves the intensity on by a frame
+    fn step(&mut self) {
         if self.direction_up {
             self.current_intensity += self.speed;
             if self.current_intensity >= RENDERS_PER_SECOND as f32 / 2.0 {
@@ -94,6 +108,12 @@ impl LedPattern for Breathing {
                 }
             }
         }
+    }
+}
+
+impl LedPattern for Breathing {
+    fn next(&mut self) -> &[Rgb] {
+        self.step();
 
         for (max, curr) in self.rgbs_max.iter().zip(self.rgbs_current.iter_mut()) {
             *curr = *max;
@@ -103,12 +123,36 @@ impl LedPattern for Breathing {
         &self.rgbs_current
     }
 
+    // slow breathing only passes a few whole values per channel, so the fractions in
+    // between are kept for the dithering
+    fn next16(&mut self, out: &mut [Rgb16]) {
+        self.step();
+
+        let scale = self.current_intensity / 100.0;
+        for (max, o) in self.rgbs_max.iter().zip(out.iter_mut()) {
+            *o = Rgb16::scaled(max, scale);
+        }
+    }
+
     fn beat(&mut self, _beat_info: &BeatCount) {}
 
     fn size(&self) -> usize {
         self.rgbs_max.len()
     }
 
+    fn reseed(&mut self) {
+        self.rng = get_rng();
+        self.colors.rewind();
+        self.current_intensity = 0.0;
+        self.direction_up = true;
+        self.switch_colors();
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = Some(palette.clone());
+        self.switch_colors();
+    }
+
     fn from_str(args: &str) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -139,11 +183,12 @@ fn parse_mode(input: &str) -> IResult<&str, BreathingMode> {
     ))(input)
 }
 
-static COMMAND_HELP: &str = "s<float> - speed (frequency 1/s); I<u8> - max intensity";
+static COMMAND_HELP: &str =
+    "s<float> - speed (frequency 1/s); I<u8> - max intensity; r<strategy> - random colors";
 
 impl PatternCommand for Breathing {
     fn execute_command(&mut self, command: &str) -> anyhow::Result<()> {
-        let cmds = command.split(',');
+        let cmds = command::split_args(command);
 
         for cmd in cmds {
             let set_cmd = cmd.as_bytes()[0] as char;
@@ -175,6 +220,11 @@ impl PatternCommand for Breathing {
                         .map_err(|_| anyhow!("Invalid BreathingMode. Use [s,d,m]!"))?;
                     self.mode = mode;
                 }
+                'r' => {
+                    self.colors = RandomColors::try_from(&cmd[1..])?;
+                    self.palette = None;
+                    self.switch_colors();
+                }
                 _ => return invalid_cmd("Breathing", cmd, COMMAND_HELP),
             };
         }
@@ -1,5 +1,4 @@
-use alloc::{vec, vec::Vec};
-use esp_hal::rng::Rng;
+use alloc::{boxed::Box, vec, vec::Vec};
 use nom::{
     bytes::complete::tag,
     character::complete::{alpha0, u32},
@@ -8,9 +7,9 @@ use nom::{
 
 use crate::{
     beat::BeatCount,
-    color::Rgb,
+    color::{palette::Palette, random::RandomColors, Rgb},
     patterns::{command, invalid_cmd},
-    util::random::get_rng,
+    util::random::{get_rng, RandomSource},
 };
 
 use super::{LedPattern, PatternCommand, PatternSpeed};
@@ -49,7 +48,8 @@ pub struct CaterPillars {
     step_counter: usize,                 // internal next() step counter
     spawn_rate: usize,                   // every n next() spawns a new caterpillar
     new_pillar_params: CreationParams,
-    rng: Rng,
+    colors: Option<RandomColors>, // replaces the base colors with their variation if set
+    rng: Box<dyn RandomSource>,
 }
 
 impl CaterPillar {
@@ -98,7 +98,7 @@ impl CaterPillars {
         n_leds: usize,
         beat_reaction: Option<PatternSpeed>,
         spawn_rate: usize,
-        rng: Rng,
+        rng: Box<dyn RandomSource>,
     ) -> Self {
         Self {
             rgbs: vec![Rgb::default(); n_leds],
@@ -116,6 +116,7 @@ impl CaterPillars {
                 body_color: Rgb::from("105010").unwrap(),
                 body_color_variation: Rgb::from("102010").unwrap(),
             },
+            colors: None,
             rng,
         }
     }
@@ -123,22 +124,33 @@ impl CaterPillars {
     fn add_new_caterpillar(&mut self) {
         let p = &self.new_pillar_params;
 
+        let (head_color, body_color) = match self.colors.as_mut() {
+            Some(colors) => (
+                colors.next(self.rng.as_mut(), p.head_color.max_channel()),
+                colors.next(self.rng.as_mut(), p.body_color.max_channel()),
+            ),
+            None => (
+                Rgb::random_with_variation(
+                    &p.head_color,
+                    &p.head_color_variation,
+                    self.rng.as_mut(),
+                ),
+                Rgb::random_with_variation(
+                    &p.body_color,
+                    &p.body_color_variation,
+                    self.rng.as_mut(),
+                ),
+            ),
+        };
+
         let mut new_cp = CaterPillar {
             pos: (0, 0),
             goal: 0,
             lengths: p.lengths,
             speeds: p.speeds,
             waiting_time: p.waiting_time,
-            head_color: Rgb::random_with_variation(
-                &p.head_color,
-                &p.head_color_variation,
-                &mut self.rng,
-            ),
-            body_color: Rgb::random_with_variation(
-                &p.body_color,
-                &p.body_color_variation,
-                &mut self.rng,
-            ),
+            head_color,
+            body_color,
             head_moving: false,
             wait_counter: None,
         };
@@ -200,15 +212,12 @@ impl LedPattern for CaterPillars {
         // and instead the needed movement per step is calculated for each caterpillar
         // to end the each movement on a beat, for that, the `needs_to_finish` bool is
         // used as a signal to finish the current movement
-        if self.beat_reaction.is_some() {
-            if self.needs_to_finish {
-                self.needs_to_finish = false;
+        if self.beat_reaction.is_some() && self.needs_to_finish {
+            self.needs_to_finish = false;
 
-                for cp in self.caterpillars.iter_mut() {
-                    cp.finish_current_move();
-                    cp.init_next_move();
-                }
-            } else {
+            for cp in self.caterpillars.iter_mut() {
+                cp.finish_current_move();
+                cp.init_next_move();
             }
         }
 
@@ -241,6 +250,25 @@ impl LedPattern for CaterPillars {
         self.rgbs.len()
     }
 
+    fn reseed(&mut self) {
+        self.rng = get_rng();
+        self.caterpillars.clear();
+        self.step_counter = 0;
+        self.needs_to_finish = false;
+        self.rgbs.fill(Rgb::default());
+        if let Some(colors) = self.colors.as_mut() {
+            colors.rewind();
+        }
+    }
+
+    // heads get the primary, bodies the secondary color, keeping their brightness
+    fn set_palette(&mut self, palette: &Palette) {
+        let p = &mut self.new_pillar_params;
+        p.head_color = palette.primary().limited(p.head_color.max_channel());
+        p.body_color = palette.secondary().limited(p.body_color.max_channel());
+        self.colors = None;
+    }
+
     fn from_str(args: &str) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -273,12 +301,12 @@ impl LedPattern for CaterPillars {
 
 static COMMAND_HELP: &str =
     "b - Beat reaction; s<int> - spawn rate;
-L<tuple> - lengths; S<tuple> - speeds; W<int> - waiting time; H<rgb> - head color base; h<rgb> - head color variation; T<rgb> - body color base; t<rgb> - body color variation
+L<tuple> - lengths; S<tuple> - speeds; W<int> - waiting time; H<rgb> - head color base; h<rgb> - head color variation; T<rgb> - body color base; t<rgb> - body color variation; u<degrees> - rotate the hue of head and body color; a<0..=100> - saturation of head and body color; r<strategy>/rbase - random colors instead of base and variation
 ";
 
 impl PatternCommand for CaterPillars {
     fn execute_command(&mut self, command: &str) -> anyhow::Result<()> {
-        let cmds = command.split(',');
+        let cmds = command::split_args(command);
 
         log::info!("{}", command);
 
@@ -326,6 +354,26 @@ impl PatternCommand for CaterPillars {
                 't' => {
                     self.new_pillar_params.body_color_variation = command::parse_rgb(&cmd[1..])?;
                 }
+                'u' => {
+                    let degrees = command::parse::<i32>(&cmd[1..])?;
+                    self.new_pillar_params.head_color.rotate_hue(degrees);
+                    self.new_pillar_params.body_color.rotate_hue(degrees);
+                }
+                'a' => {
+                    let percent = command::parse::<u8>(&cmd[1..])?;
+                    if percent > 100 {
+                        return Err(anyhow!("Saturation {} is not in 0..=100%", percent));
+                    }
+                    let saturation = (percent as u32 * 255 / 100) as u8;
+                    self.new_pillar_params.head_color.set_saturation(saturation);
+                    self.new_pillar_params.body_color.set_saturation(saturation);
+                }
+                'r' => {
+                    self.colors = match &cmd[1..] {
+                        "base" => None,
+                        strategy => Some(RandomColors::try_from(strategy)?),
+                    };
+                }
                 _ => return invalid_cmd("CaterPillars", cmd, COMMAND_HELP),
             };
         }
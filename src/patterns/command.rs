@@ -1,19 +1,84 @@
 use anyhow::anyhow;
 use core::str::FromStr;
 use nom::{
-    bytes::complete::{tag, take_while_m_n},
-    character::{complete::u32, is_hex_digit},
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{u16, u32, u8},
     error::ErrorKind,
-    sequence::pair,
+    sequence::{delimited, pair, tuple},
     IResult,
 };
 
 use alloc::vec::Vec;
 
-use crate::color::Rgb;
+use crate::color::{
+    hsv::{Hsl, Hsv},
+    Rgb,
+};
 
+/// Parses a color in one of the supported notations:
+/// - hex with or without '#': `#f80`, `#ff8000`, `ff8000`
+/// - a color name: `red`, `warmwhite` (see `color::NAMED_COLORS`)
+/// - `hsv(h,s,v)` / `hsl(h,s,l)` with the hue in degrees and the rest in percent
+/// - a color temperature in Kelvin: `2700K`
 pub fn parse_rgb(command: &str) -> anyhow::Result<Rgb> {
-    Rgb::from(command).map_err(|_| anyhow!("Couldnt parse the RGB value!"))
+    if command.is_empty() {
+        return Err(anyhow!("Missing color"));
+    }
+
+    if let Some(hex) = command.strip_prefix('#') {
+        return Rgb::from(hex);
+    }
+
+    if command.starts_with("hs") {
+        let (remainder, rgb) = hue_color(command).map_err(|_| {
+            anyhow!(
+                "Couldnt parse the color {:?}! Use hsv(h,s,v) or hsl(h,s,l) with h in 0..360 and s,v,l in 0..=100",
+                command
+            )
+        })?;
+        if !remainder.is_empty() {
+            return Err(anyhow!("Unexpected {:?} after the color", remainder));
+        }
+        return Ok(rgb);
+    }
+
+    if let Some(kelvin) = command.strip_suffix(['K', 'k']) {
+        if kelvin.chars().all(|c| c.is_ascii_digit()) {
+            let kelvin = kelvin
+                .parse::<u32>()
+                .map_err(|_| anyhow!("Invalid color temperature {:?}", command))?;
+            return Rgb::from_kelvin(kelvin);
+        }
+    }
+
+    if let Some(rgb) = Rgb::named(command) {
+        return Ok(rgb);
+    }
+
+    // plain hex without the '#', as it was accepted before
+    if command.len() == 6 && command.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Rgb::from(command);
+    }
+
+    Err(anyhow!(
+        "Unknown color {:?}! Use #rgb, #rrggbb, hsv(h,s,v), a temperature like 2700K or a name like red",
+        command
+    ))
+}
+
+/// Splits a command at the commas that are not inside of parentheses, so that arguments
+/// like `hsv(0,100,100)` stay in one piece.
+pub fn split_args(command: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0;
+    command.split(move |c| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => (),
+        };
+        c == ',' && depth == 0
+    })
 }
 
 pub fn parse<T>(arg: &str) -> anyhow::Result<T>
@@ -62,18 +127,32 @@ pub fn range_tuple(input: &str) -> IResult<&str, (u32, u32)> {
     Ok((remainder, (first, second)))
 }
 
-pub fn hex_rgb(input: &str) -> IResult<&str, Rgb> {
-    let (remainder, (_, rgb_string)) =
-        pair(tag("#"), take_while_m_n(6, 6, |c| is_hex_digit(c as u8)))(input)?;
-
-    let rgb = Rgb::from(rgb_string);
-    if rgb.is_err() {
-        // # use nom::{Err, error::{Error, ErrorKind}, Needed, IResult};
-        Err(nom::Err::Error(nom::error::Error::new(
-            "Hex parse error",
-            ErrorKind::Fail,
-        )))
-    } else {
-        Ok((remainder, rgb.unwrap()))
+/// Parses `hsv(h,s,v)` or `hsl(h,s,l)` with the hue in degrees and the other values in
+/// percent.
+pub fn hue_color(input: &str) -> IResult<&str, Rgb> {
+    let (remainder, (kind, (h, _, s, _, v))) = pair(
+        alt((tag("hsv"), tag("hsl"))),
+        delimited(tag("("), tuple((u16, tag(","), u8, tag(","), u8)), tag(")")),
+    )(input)?;
+
+    if h >= 360 || s > 100 || v > 100 {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            ErrorKind::Verify,
+        )));
     }
+
+    let percent = |p: u8| (p as u32 * 255 / 100) as u8;
+    let rgb = if kind == "hsv" {
+        Hsv::new(h, percent(s), percent(v)).into()
+    } else {
+        Hsl {
+            h,
+            s: percent(s),
+            l: percent(v),
+        }
+        .into()
+    };
+
+    Ok((remainder, rgb))
 }
@@ -0,0 +1,236 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+use anyhow::anyhow;
+
+use crate::{
+    beat::BeatCount,
+    color::{blend::BlendMode, palette::Palette, Rgb},
+};
+
+use super::{
+    command, invalid_cmd, pattern_with_args_from_command, LedPattern, PatternCommand, PatternKind,
+};
+
+struct Layer {
+    pattern: Box<dyn LedPattern>,
+    mode: BlendMode,
+    opacity: u8, // 0..=255
+}
+
+/// Stacks patterns over the same LEDs, the first layer is at the bottom.
+pub struct Layered {
+    rgbs: Vec<Rgb>,
+    layers: Vec<Layer>,
+    palette: Option<Palette>, // also applied to layers added later
+}
+
+impl Layered {
+    fn new(n_leds: usize) -> Self {
+        Self {
+            rgbs: vec![Rgb::default(); n_leds],
+            layers: vec![],
+            palette: None,
+        }
+    }
+
+    fn add(
+        &mut self,
+        mut pattern: Box<dyn LedPattern>,
+        mode: BlendMode,
+        opacity: u8,
+    ) -> anyhow::Result<()> {
+        if pattern.size() != self.rgbs.len() {
+            return Err(anyhow!(
+                "All layers need the same size; expected {} LEDs, got {}",
+                self.rgbs.len(),
+                pattern.size()
+            ));
+        }
+
+        if let Some(palette) = self.palette.as_ref() {
+            pattern.set_palette(palette);
+        }
+
+        self.layers.push(Layer {
+            pattern,
+            mode,
+            opacity,
+        });
+
+        Ok(())
+    }
+
+    /// Parses one layer given as `<pattern>,<blend mode>,<opacity in %>`.
+    fn parse_layer(layer: &[&str]) -> anyhow::Result<(Box<dyn LedPattern>, BlendMode, u8)> {
+        let [pattern, mode, opacity] = layer else {
+            return Err(anyhow!(
+                "Layers need a pattern, a blend mode and an opacity, got {:?}",
+                layer
+            ));
+        };
+
+        let (remainder, (pattern_kind, args)) = pattern_with_args_from_command(pattern).map_err(
+            |_: nom::Err<nom::error::Error<&str>>| anyhow!("Could not parse pattern and args!"),
+        )?;
+        if !remainder.is_empty() {
+            return Err(anyhow!(
+                "Unexpected {:?} after the layer pattern",
+                remainder
+            ));
+        }
+        let pattern = PatternKind::try_from(pattern_kind)?.to_pattern(args)?;
+
+        Ok((
+            pattern,
+            BlendMode::try_from(*mode)?,
+            parse_opacity(opacity)?,
+        ))
+    }
+
+    fn layer(&mut self, index: usize) -> anyhow::Result<&mut Layer> {
+        let n_layers = self.layers.len();
+        self.layers
+            .get_mut(index)
+            .ok_or_else(|| anyhow!("Layer index out of range {} / {}", index, n_layers))
+    }
+}
+
+fn parse_opacity(arg: &str) -> anyhow::Result<u8> {
+    let percent = command::parse::<u8>(arg)?;
+    if percent > 100 {
+        return Err(anyhow!("Opacity {} is not in 0..=100%", percent));
+    }
+
+    Ok((percent as u32 * 255 / 100) as u8)
+}
+
+impl LedPattern for Layered {
+    fn next(&mut self) -> &[Rgb] {
+        self.rgbs.iter_mut().for_each(|rgb| *rgb = Rgb::default());
+
+        for layer in self.layers.iter_mut() {
+            let top = layer.pattern.next();
+            for (base, top) in self.rgbs.iter_mut().zip(top) {
+                *base = layer.mode.blend(*base, *top, layer.opacity);
+            }
+        }
+
+        &self.rgbs
+    }
+
+    fn beat(&mut self, beat_info: &BeatCount) {
+        for layer in self.layers.iter_mut() {
+            layer.pattern.beat(beat_info);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.rgbs.len()
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = Some(palette.clone());
+
+        for layer in self.layers.iter_mut() {
+            layer.pattern.set_palette(palette);
+        }
+    }
+
+    fn reseed(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.pattern.reseed();
+        }
+    }
+
+    // Arguments: <n_leds>,<pattern>,<blend mode>,<opacity in %>,... (bottom layer first)
+    fn from_str(args: &str) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let args = command::split_args(args).collect::<Vec<_>>();
+        let (n_leds, layers) = args
+            .split_first()
+            .ok_or_else(|| anyhow!("No size arg given!"))?;
+
+        let mut res = Self::new(command::parse::<usize>(n_leds)?);
+        for layer in layers.chunks(3) {
+            let (pattern, mode, opacity) = Self::parse_layer(layer)?;
+            res.add(pattern, mode, opacity)?;
+        }
+
+        Ok(res)
+    }
+}
+
+static COMMAND_HELP: &str = "a<pattern>,<mode>,<opacity> - add layer on top; l<n>m<mode> - blend mode (normal, alpha, add, multiply, screen, max, difference); l<n>o<0..=100> - opacity; l<n>c<cmd> - layer command; l<n>R - remove layer";
+
+impl PatternCommand for Layered {
+    fn execute_command(&mut self, command: &str) -> anyhow::Result<()> {
+        let Some(set_cmd) = command.chars().next() else {
+            return invalid_cmd("Layered", command, COMMAND_HELP);
+        };
+
+        match set_cmd {
+            'a' => {
+                let layer = command::split_args(&command[1..]).collect::<Vec<_>>();
+                let (pattern, mode, opacity) = Self::parse_layer(&layer)?;
+                self.add(pattern, mode, opacity)?;
+            }
+            'l' => {
+                // the digits of the layer index are followed by the sub command
+                let args = &command[1..];
+                let digits = args
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(args.len());
+                let (index, sub_cmd) = args.split_at(digits);
+                let index = index
+                    .parse::<usize>()
+                    .map_err(|_| anyhow!("Missing layer index in {:?}", command))?;
+                let arg = sub_cmd.get(1..).unwrap_or_default();
+
+                match sub_cmd.get(..1) {
+                    Some("m") => self.layer(index)?.mode = BlendMode::try_from(arg)?,
+                    Some("o") => self.layer(index)?.opacity = parse_opacity(arg)?,
+                    Some("c") => self.layer(index)?.pattern.execute_command(arg)?,
+                    Some("R") => {
+                        self.layer(index)?;
+                        self.layers.remove(index);
+                    }
+                    _ => return invalid_cmd("Layered", command, COMMAND_HELP),
+                }
+            }
+            _ => return invalid_cmd("Layered", command, COMMAND_HELP),
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patterns::tunable_white::TunableWhite;
+
+    #[test]
+    fn layers_with_multi_digit_indices() {
+        let mut layered = Layered::new(4);
+        for _ in 0..12 {
+            layered
+                .add(
+                    Box::new(TunableWhite::new(4, 2700, 100)),
+                    BlendMode::Add,
+                    255,
+                )
+                .unwrap();
+        }
+
+        layered.execute_command("l10mmax").unwrap();
+        layered.execute_command("l11o50").unwrap();
+        assert_eq!(layered.layers[10].mode, BlendMode::Max);
+        assert_eq!(layered.layers[11].opacity, parse_opacity("50").unwrap());
+
+        layered.execute_command("l11R").unwrap();
+        assert_eq!(layered.layers.len(), 11);
+        assert!(layered.execute_command("l11o50").is_err());
+        assert!(layered.execute_command("lo50").is_err());
+    }
+}
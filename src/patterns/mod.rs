@@ -2,6 +2,7 @@
 //!
 //! If you want to create a new pattern, copy the following snippet:
 //!
+//! ```ignore
 //! struct NewPattern {
 //!     rgbs: Vec<Rgb>,
 //!     // ...
@@ -25,6 +26,17 @@
 //!     fn size(&self) -> usize {
 //!         todo!();
 //!     }
+//!
+//!     // optional, if the pattern uses colors
+//!     fn set_palette(&mut self, palette: &Palette) {
+//!         todo!();
+//!     }
+//!
+//!     // optional, if the pattern uses random numbers
+//!     fn reseed(&mut self) {
+//!         self.rng = get_rng();
+//!         // reset everything the random numbers were used for
+//!     }
 //! }
 //!
 //! impl PatternCommand for NewPattern {
@@ -32,40 +44,63 @@
 //!         todo!();
 //!     }
 //! }
+//! ```
 
-use crate::{beat::BeatCount, color::Rgb};
+use crate::{
+    beat::BeatCount,
+    color::{palette::Palette, Rgb, Rgb16},
+};
 use alloc::boxed::Box;
 use anyhow::{anyhow, Result};
 use background::Background;
 use breathing::Breathing;
 use caterpillar::CaterPillars;
+use layered::Layered;
 use nom::{
-    bytes::complete::{tag, take_until, take_while},
+    bytes::complete::{tag, take_until},
     sequence::delimited,
     IResult,
 };
 use partitioned::PartitionedPatterns;
 use shooting_star::ShootingStar;
 use strobe::Strobe;
+use tunable_white::TunableWhite;
 
 pub mod background;
 pub mod breathing;
 pub mod caterpillar;
 pub mod command;
+pub mod layered;
 pub mod partitioned;
 pub mod shooting_star;
 pub mod strobe;
+pub mod tunable_white;
 
 pub trait LedPattern: Send + Sync + PatternCommand {
     // render function to get the next RGB state of the pattern
     fn next(&mut self) -> &[Rgb];
 
+    // like next(), but with 8 fractional bits per channel, which the render pipeline
+    // dithers; patterns with slow fades override it to keep their fractions
+    fn next16(&mut self, out: &mut [Rgb16]) {
+        for (o, rgb) in out.iter_mut().zip(self.next()) {
+            *o = (*rgb).into();
+        }
+    }
+
     // react to a music beat
     fn beat(&mut self, beat_info: &BeatCount);
 
     // number of LEDs inside the pattern
     fn size(&self) -> usize;
 
+    // use the colors of the palette instead of random colors
+    fn set_palette(&mut self, _palette: &Palette) {}
+
+    // get a new random source via util::random::get_rng and start the animation over,
+    // so that after a new seed was set the same show is replayed; settings are kept
+    fn reseed(&mut self) {}
+
     fn from_str(args: &str) -> anyhow::Result<Self>
     where
         Self: Sized;
@@ -80,9 +115,11 @@ enum PatternKind {
     Background,
     Breathing,
     Caterpillar,
+    Layered,
     Partitioned,
     ShootingStar,
     Strobe,
+    TunableWhite,
 }
 
 impl TryFrom<&str> for PatternKind {
@@ -93,10 +130,12 @@ impl TryFrom<&str> for PatternKind {
             "br" => Ok(PatternKind::Breathing),
             "ba" => Ok(PatternKind::Background),
             "cat" => Ok(PatternKind::Caterpillar),
+            "ly" => Ok(PatternKind::Layered),
             "pt" => Ok(PatternKind::Partitioned),
             "shst" => Ok(PatternKind::ShootingStar),
             "str" => Ok(PatternKind::Strobe),
-            c => Err(anyhow!("Invalid PatternKind {:?}. Available types are: br - Breathing; ba - Background; cat - CaterPillars; pt - Partitioned; shst - ShootingStar; str - Strobe", c)),
+            "tw" => Ok(PatternKind::TunableWhite),
+            c => Err(anyhow!("Invalid PatternKind {:?}. Available types are: br - Breathing; ba - Background; cat - CaterPillars; ly - Layered; pt - Partitioned; shst - ShootingStar; str - Strobe; tw - TunableWhite", c)),
         }
     }
 }
@@ -107,9 +146,11 @@ impl PatternKind {
             PatternKind::Background => Box::new(Background::from_str(args)?),
             PatternKind::Breathing => Box::new(Breathing::from_str(args)?),
             PatternKind::Caterpillar => Box::new(CaterPillars::from_str(args)?),
+            PatternKind::Layered => Box::new(Layered::from_str(args)?),
             PatternKind::Partitioned => Box::new(PartitionedPatterns::from_str(args)?),
             PatternKind::ShootingStar => Box::new(ShootingStar::from_str(args)?),
             PatternKind::Strobe => Box::new(Strobe::from_str(args)?),
+            PatternKind::TunableWhite => Box::new(TunableWhite::from_str(args)?),
         };
 
         Ok(res)
@@ -120,57 +161,98 @@ pub fn pattern_with_args_from_command(input: &str) -> IResult<&str, (&str, &str)
     let (remainder, pattern_kind) = take_until("(")(input)?;
 
     // parse the args for pattern creation between the parentheses
-    let (remainder, args) = delimited(
-        tag("("),
-        take_while(|c: char| c.is_alphanumeric() || c == ',' || c == '.'),
-        tag(")"),
-    )(remainder)?;
+    let (remainder, args) = delimited(tag("("), balanced_args, tag(")"))(remainder)?;
 
     Ok((remainder, (pattern_kind, args)))
 }
 
+/// Takes the args up to the closing parenthesis, skipping over nested ones like in
+/// `hsv(0,100,100)` or nested patterns.
+fn balanced_args(input: &str) -> IResult<&str, &str> {
+    let mut depth = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Ok((&input[i..], &input[..i])),
+            ')' => depth -= 1,
+            c if c.is_alphanumeric() || ",.#".contains(c) => (),
+            _ => break,
+        }
+    }
+
+    Err(nom::Err::Error(nom::error::Error::new(
+        input,
+        nom::error::ErrorKind::TakeWhile1,
+    )))
+}
+
 #[derive(Copy, Clone, Debug, Default)]
 pub enum PatternSpeed {
     N32,
+    N16T, // 16th triplets
     N16,
+    N8T, // 8th triplets
     N8,
     #[default]
     N4,
     N2,
     N1,
+    B2, // every 2 bars
+    B4,
+    B8,
+    Phrase, // first beat of every phrase
 }
 
 impl PatternSpeed {
     fn faster(&mut self) {
         *self = match self {
             Self::N32 => Self::N32,
+            Self::N16T => Self::N32,
             Self::N16 => Self::N32,
+            Self::N8T => Self::N16T,
             Self::N8 => Self::N16,
             Self::N4 => Self::N8,
             Self::N2 => Self::N4,
             Self::N1 => Self::N2,
+            Self::B2 => Self::N1,
+            Self::B4 => Self::B2,
+            Self::B8 => Self::B4,
+            Self::Phrase => Self::N1,
         }
     }
 
     fn slower(&mut self) {
         *self = match self {
             Self::N32 => Self::N16,
+            Self::N16T => Self::N8T,
             Self::N16 => Self::N8,
+            Self::N8T => Self::N4,
             Self::N8 => Self::N4,
             Self::N4 => Self::N2,
             Self::N2 => Self::N1,
-            Self::N1 => Self::N1,
+            Self::N1 => Self::B2,
+            Self::B2 => Self::B4,
+            Self::B4 => Self::B8,
+            Self::B8 => Self::B8,
+            Self::Phrase => Self::Phrase,
         }
     }
 
     fn is_triggered(&self, beat_info: &BeatCount) -> bool {
         match self {
-            Self::N32 => true,
+            Self::N32 => beat_info.n32th.is_some(),
+            Self::N16T => beat_info.n16th_triplet.is_some(),
             Self::N16 => beat_info.n16th.is_some(),
+            Self::N8T => beat_info.n8th_triplet.is_some(),
             Self::N8 => beat_info.n8th.is_some(),
             Self::N4 => beat_info.n_quarter.is_some(),
             Self::N2 => beat_info.n_half.is_some(),
             Self::N1 => beat_info.n_full.is_some(),
+            // the bars are counted from the last tap or downbeat
+            Self::B2 => beat_info.n_full.is_some() && beat_info.bar % 2 == 0,
+            Self::B4 => beat_info.n_full.is_some() && beat_info.bar % 4 == 0,
+            Self::B8 => beat_info.n_full.is_some() && beat_info.bar % 8 == 0,
+            Self::Phrase => beat_info.is_phrase_start(),
         }
     }
 
@@ -179,12 +261,8 @@ impl PatternSpeed {
             'f' => self.faster(),
             's' => self.slower(),
             c => {
-                let res = Self::try_from(c);
-                if res.is_err() {
-                    return Err(anyhow!("Invalid speed change parameter {}!", c));
-                } else {
-                    *self = res.unwrap();
-                }
+                *self = Self::try_from(c)
+                    .map_err(|_| anyhow!("Invalid speed change parameter {}!", c))?;
             }
         };
 
@@ -203,6 +281,12 @@ impl TryFrom<char> for PatternSpeed {
             '3' => Ok(PatternSpeed::N8),
             '4' => Ok(PatternSpeed::N16),
             '5' => Ok(PatternSpeed::N32),
+            't' => Ok(PatternSpeed::N8T),
+            'T' => Ok(PatternSpeed::N16T),
+            'd' => Ok(PatternSpeed::B2),
+            'q' => Ok(PatternSpeed::B4),
+            'o' => Ok(PatternSpeed::B8),
+            'p' => Ok(PatternSpeed::Phrase),
             _ => Err(anyhow!("Invalid PatternSpeed character {}", value)),
         }
     }
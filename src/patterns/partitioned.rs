@@ -2,7 +2,10 @@ use alloc::{boxed::Box, vec, vec::Vec};
 use anyhow::{anyhow, Error};
 use nom::bytes::complete::take_while;
 
-use crate::{beat::BeatCount, color::Rgb};
+use crate::{
+    beat::BeatCount,
+    color::{palette::Palette, Rgb, Rgb16},
+};
 use core::str;
 
 use super::{
@@ -20,6 +23,7 @@ type PatternWithStatus = (PatternSection, bool, bool);
 pub struct PartitionedPatterns {
     rgbs: Vec<Rgb>,
     patterns: Vec<PatternWithStatus>,
+    palette: Option<Palette>, // palette for all patterns, also for patterns added later
 }
 
 impl PartitionedPatterns {
@@ -27,10 +31,15 @@ impl PartitionedPatterns {
         Self {
             rgbs: vec![Rgb::default(); n_leds],
             patterns: vec![],
+            palette: None,
         }
     }
 
-    pub fn add(&mut self, pattern: Box<dyn LedPattern>, range: Option<(usize, usize)>) {
+    pub fn add(&mut self, mut pattern: Box<dyn LedPattern>, range: Option<(usize, usize)>) {
+        if let Some(palette) = self.palette.as_ref() {
+            pattern.set_palette(palette);
+        }
+
         // if no range given, get it from the last added pattern and the given pattern's size
         // this is obviously not very robust if the user adds new patterns in not-sorted order
         // So, there needs to happen some adaptation later...
@@ -52,6 +61,25 @@ impl PartitionedPatterns {
             true,
         ));
     }
+
+    /// Changes the number of LEDs. Fails if a pattern's range would not fit anymore.
+    pub fn resize(&mut self, n_leds: usize) -> anyhow::Result<()> {
+        for (i, (ps, _, _)) in self.patterns.iter().enumerate() {
+            if ps.range.1 > n_leds {
+                return Err(anyhow!(
+                    "Pattern {} ({}..{}) would be outside of the new length {}",
+                    i,
+                    ps.range.0,
+                    ps.range.1,
+                    n_leds
+                ));
+            }
+        }
+
+        self.rgbs.resize(n_leds, Rgb::default());
+
+        Ok(())
+    }
 }
 
 impl LedPattern for PartitionedPatterns {
@@ -68,6 +96,17 @@ impl LedPattern for PartitionedPatterns {
         &self.rgbs
     }
 
+    fn next16(&mut self, out: &mut [Rgb16]) {
+        out.iter_mut().for_each(|rgb| *rgb = Rgb16::default());
+
+        for (ps, render_status, _beat_status) in self.patterns.iter_mut() {
+            if *render_status {
+                let (a, b) = (ps.range.0, ps.range.1.min(out.len()));
+                ps.pattern.next16(&mut out[a.min(b)..b]);
+            }
+        }
+    }
+
     fn beat(&mut self, beat_info: &BeatCount) {
         for (ps, _render_status, beat_status) in self.patterns.iter_mut() {
             if *beat_status {
@@ -80,6 +119,20 @@ impl LedPattern for PartitionedPatterns {
         self.rgbs.len()
     }
 
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = Some(palette.clone());
+
+        for (ps, _, _) in self.patterns.iter_mut() {
+            ps.pattern.set_palette(palette);
+        }
+    }
+
+    fn reseed(&mut self) {
+        for (ps, _, _) in self.patterns.iter_mut() {
+            ps.pattern.reseed();
+        }
+    }
+
     fn from_str(args: &str) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -94,6 +147,7 @@ impl LedPattern for PartitionedPatterns {
         Ok(Self {
             rgbs: vec![Rgb::default(); size],
             patterns: vec![],
+            palette: None,
         })
     }
 }
@@ -108,6 +162,7 @@ impl PatternCommand for PartitionedPatterns {
             // "pn..." => regards pattern n
             // "g..." => global execution (stop all, resume all, etc.)
             // "a..." => add new pattern
+            // "P..." => set the palette of all patterns
 
             match cmd_bytes[0] as char {
                 'p' => {
@@ -135,6 +190,10 @@ impl PatternCommand for PartitionedPatterns {
                         'R' => {
                             self.patterns.remove(index);
                         }
+                        'P' => {
+                            let palette = Palette::try_from(&cmd[3..])?;
+                            self.patterns[index].0.pattern.set_palette(&palette);
+                        }
                         'C' => {
                             let (_, (pattern_kind, args)) =
                                 pattern_with_args_from_command(&cmd[3..]).map_err(
@@ -144,8 +203,11 @@ impl PatternCommand for PartitionedPatterns {
                                 )?;
 
                             // create the pattern with the given args
-                            let pattern: Box<dyn LedPattern> =
+                            let mut pattern: Box<dyn LedPattern> =
                                 PatternKind::try_from(pattern_kind)?.to_pattern(args)?;
+                            if let Some(palette) = self.palette.as_ref() {
+                                pattern.set_palette(palette);
+                            }
 
                             // finally, switch out the new pattern for the old one
                             self.patterns[index].0.pattern = pattern;
@@ -154,6 +216,10 @@ impl PatternCommand for PartitionedPatterns {
                     };
                 }
                 'g' => (),
+                'P' => {
+                    let palette = Palette::try_from(&cmd[1..])?;
+                    self.set_palette(&palette);
+                }
                 'a' => {
                     let input = str::from_utf8(&cmd_bytes[1..]).unwrap();
                     // adds a new pattern via the Self::add() function
@@ -198,3 +264,59 @@ impl PatternCommand for PartitionedPatterns {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        patterns::{
+            breathing::{Breathing, BreathingMode},
+            caterpillar::CaterPillars,
+            shooting_star::ShootingStar,
+            strobe::{Strobe, StrobeMode},
+        },
+        util::random::{get_rng, set_seed},
+    };
+
+    // frames of a few seconds of show, with a beat on every tick
+    fn show(rgbs: &mut PartitionedPatterns) -> Vec<Rgb> {
+        let mut beat = BeatCount::default();
+        let mut frames = vec![];
+        for _ in 0..500 {
+            rgbs.beat(&beat);
+            beat.increment(beat.signature, beat.phrase_length, beat.rate);
+            frames.extend_from_slice(rgbs.next());
+        }
+
+        frames
+    }
+
+    #[test]
+    fn same_seed_replays_the_same_show() {
+        let mut rgbs = PartitionedPatterns::new(68);
+        rgbs.add(Box::new(CaterPillars::new(30, None, 7, get_rng())), None);
+        rgbs.add(
+            Box::new(Breathing::new(10, BreathingMode::Mixed, 30, get_rng(), 3.0)),
+            None,
+        );
+        rgbs.add(
+            Box::new(Strobe::new(8, StrobeMode::Individual, get_rng(), 20)),
+            None,
+        );
+        let mut stars = ShootingStar::new(20, 500, get_rng());
+        stars.execute_command("rgolden").unwrap();
+        rgbs.add(Box::new(stars), None);
+
+        set_seed(Some(42));
+        rgbs.reseed();
+        let first = show(&mut rgbs);
+
+        set_seed(Some(42));
+        rgbs.reseed();
+        let second = show(&mut rgbs);
+        set_seed(None);
+
+        assert!(first.iter().any(|rgb| *rgb != Rgb::default()));
+        assert_eq!(first, second);
+    }
+}
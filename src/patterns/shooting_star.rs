@@ -1,13 +1,12 @@
-use alloc::{vec, vec::Vec};
+use alloc::{boxed::Box, vec, vec::Vec};
 use anyhow::anyhow;
-use esp_hal::rng::Rng;
 use nom::{bytes::complete::tag, character::complete::u32, sequence::tuple};
 
 use crate::{
     beat::BeatCount,
-    color::Rgb,
+    color::{palette::Palette, random::RandomColors, Rgb},
     patterns::{command, invalid_cmd},
-    util::random::get_rng,
+    util::random::{get_rng, RandomSource},
     MAX_INTENSITY,
 };
 
@@ -21,10 +20,12 @@ pub struct ShootingStar {
     stars: Vec<Star>,
     shoot_interval: PatternSpeed,
     step_counter: usize,
-    rng: Rng,
+    rng: Box<dyn RandomSource>,
     max_intensity: usize,
     tail_length: usize,
     star_steps_per_move: usize,
+    palette: Option<Palette>,
+    colors: RandomColors, // used if there is no palette
 }
 
 #[derive(Default, Debug, Copy, Clone)]
@@ -36,7 +37,7 @@ struct Star {
 }
 
 impl ShootingStar {
-    pub fn new(n_leds: usize, speed: usize, rng: Rng) -> Self {
+    pub fn new(n_leds: usize, speed: usize, rng: Box<dyn RandomSource>) -> Self {
         ShootingStar {
             rgbs_current: vec![Rgb::default(); n_leds],
             speed,
@@ -47,6 +48,8 @@ impl ShootingStar {
             max_intensity: MAX_INTENSITY as usize,
             tail_length: 5,
             star_steps_per_move: 2,
+            palette: None,
+            colors: RandomColors::default(),
         }
     }
 
@@ -113,12 +116,32 @@ impl LedPattern for ShootingStar {
         self.rgbs_current.len()
     }
 
+    fn reseed(&mut self) {
+        self.rng = get_rng();
+        self.stars.clear();
+        self.step_counter = 0;
+        self.rgbs_current.fill(Rgb::default());
+        self.colors.rewind();
+        if let Some(palette) = self.palette.as_mut() {
+            palette.rewind();
+        }
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = Some(palette.clone());
+    }
+
     fn beat(&mut self, beat_info: &BeatCount) {
         if !self.shoot_interval.is_triggered(beat_info) {
             return;
         }
 
-        let color = Rgb::random(&mut self.rng, self.max_intensity as u8);
+        let color = match self.palette.as_mut() {
+            Some(palette) => palette.next().unwrap().limited(self.max_intensity as u8),
+            None => self
+                .colors
+                .next(self.rng.as_mut(), self.max_intensity as u8),
+        };
 
         self.shoot(color, self.star_steps_per_move, self.tail_length)
     }
@@ -142,11 +165,11 @@ impl LedPattern for ShootingStar {
     }
 }
 
-static COMMAND_HELP: &str = "b<char> - Beat reaction; s<int> - speed; I<u8> - intensity; S<int> - speed; l<int> - tail length;";
+static COMMAND_HELP: &str = "b<char> - Beat reaction; s<int> - speed; I<u8> - intensity; S<int> - speed; l<int> - tail length; r<strategy> - random colors;";
 
 impl PatternCommand for ShootingStar {
     fn execute_command(&mut self, command: &str) -> anyhow::Result<()> {
-        let cmds = command.split(',');
+        let cmds = command::split_args(command);
 
         log::info!("{}", command);
 
@@ -185,6 +208,10 @@ impl PatternCommand for ShootingStar {
                         s.tail_length = length;
                     }
                 }
+                'r' => {
+                    self.colors = RandomColors::try_from(&cmd[1..])?;
+                    self.palette = None;
+                }
                 _ => return invalid_cmd("ShootingStar", cmd, COMMAND_HELP),
             };
         }
@@ -1,21 +1,20 @@
 use super::{LedPattern, PatternCommand, PatternSpeed};
 use crate::{
     beat::BeatCount,
-    color::Rgb,
-    patterns::invalid_cmd,
-    util::random::get_rng,
+    color::{palette::Palette, random::RandomColors, Rgb},
+    patterns::{command, invalid_cmd},
+    util::random::{get_rng, RandomSource},
     RENDERS_PER_SECOND,
 };
-use alloc::{vec, vec::Vec};
+use alloc::{boxed::Box, vec, vec::Vec};
 use anyhow::anyhow;
-use esp_hal::rng::Rng;
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{alpha1, u32},
     combinator::value,
     sequence::tuple,
-    AsBytes, IResult,
+    IResult,
 };
 
 #[derive(Clone)]
@@ -31,16 +30,19 @@ pub struct Strobe {
     counters: Vec<usize>,
     speed: usize, // switches per second; if 0, listens to beat
     mode: StrobeMode,
-    rng: Rng,
+    rng: Box<dyn RandomSource>,
     max_intensity: u8,
     beat_reaction: PatternSpeed,
+    palette: Option<Palette>,
+    colors: Option<RandomColors>, // random flash colors if there is no palette
+    color: Option<Rgb>,           // color of the current flash; white if None
 }
 
 // how many next() calls the leds stay turned on for a strobe
 const MAX_STROBE_ON_DURATION: usize = 3;
 
 impl Strobe {
-    pub fn new(n_leds: usize, mode: StrobeMode, rng: Rng, speed: usize) -> Self {
+    pub fn new(n_leds: usize, mode: StrobeMode, rng: Box<dyn RandomSource>, speed: usize) -> Self {
         let mut ret = Self {
             rgbs: vec![Rgb::default(); n_leds],
             status: vec![false; n_leds],
@@ -50,30 +52,47 @@ impl Strobe {
             rng,
             max_intensity: 50,
             beat_reaction: PatternSpeed::default(),
+            palette: None,
+            colors: None,
+            color: None,
         };
 
-        match ret.mode {
+        ret.init_status();
+
+        ret
+    }
+
+    // sets the LEDs that are on at the start
+    fn init_status(&mut self) {
+        self.status.fill(false);
+        self.counters.fill(0);
+
+        match self.mode {
             StrobeMode::Single => {
-                let first = ret.rng.random() as usize % n_leds;
+                let first = self.rng.random() as usize % self.size();
 
-                ret.status[first] = true;
+                self.status[first] = true;
             }
             StrobeMode::Individual => {
-                for el in ret.status.iter_mut() {
-                    *el = ret.rng.random() % 2 == 1;
+                for el in self.status.iter_mut() {
+                    *el = self.rng.random() % 2 == 1;
                 }
 
-                for el in ret.counters.iter_mut() {
-                    *el = ret.rng.random() as usize % RENDERS_PER_SECOND;
+                for el in self.counters.iter_mut() {
+                    *el = self.rng.random() as usize % RENDERS_PER_SECOND;
                 }
             }
             StrobeMode::Unison => {}
         }
-
-        ret
     }
 
     fn trigger(&mut self) {
+        if let Some(palette) = self.palette.as_mut() {
+            self.color = palette.next();
+        } else if let Some(colors) = self.colors.as_mut() {
+            self.color = Some(colors.next(self.rng.as_mut(), u8::MAX));
+        }
+
         match self.mode {
             StrobeMode::Single => {
                 let on_idx = self.status.iter().position(|x| *x).unwrap_or(0);
@@ -152,13 +171,18 @@ impl LedPattern for Strobe {
             }
         }
 
+        let color = match self.color {
+            Some(color) => color.limited(self.max_intensity),
+            None => Rgb {
+                r: self.max_intensity,
+                g: self.max_intensity,
+                b: self.max_intensity,
+            },
+        };
+
         for (status, rgb) in self.status.iter().zip(self.rgbs.iter_mut()) {
             if *status {
-                *rgb = Rgb {
-                    r: self.max_intensity,
-                    g: self.max_intensity,
-                    b: self.max_intensity,
-                };
+                *rgb = color;
             } else {
                 *rgb = Rgb::default();
             }
@@ -184,6 +208,22 @@ impl LedPattern for Strobe {
         self.rgbs.len()
     }
 
+    fn reseed(&mut self) {
+        self.rng = get_rng();
+        self.color = None;
+        if let Some(colors) = self.colors.as_mut() {
+            colors.rewind();
+        }
+        if let Some(palette) = self.palette.as_mut() {
+            palette.rewind();
+        }
+        self.init_status();
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = Some(palette.clone());
+    }
+
     fn from_str(command: &str) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -215,11 +255,11 @@ fn parse_mode(input: &str) -> IResult<&str, StrobeMode> {
 }
 
 static COMMAND_HELP: &str =
-    "b<char> - Beat reaction; s<int> - speed; I<u8> - intensity; m[s,i,u] - mode switch";
+    "b<char> - Beat reaction; s<int> - speed; I<u8> - intensity; m[s,i,u] - mode switch; r<strategy>/rwhite - random colors";
 
 impl PatternCommand for Strobe {
     fn execute_command(&mut self, command: &str) -> anyhow::Result<()> {
-        let cmds = command.split(',');
+        let cmds = command::split_args(command);
 
         log::info!("{}", command);
 
@@ -263,6 +303,14 @@ impl PatternCommand for Strobe {
                         parse_mode(arg).map_err(|_| anyhow!("Invalid StrobeMode. Use [s,i,u]!"))?;
                     self.mode = mode;
                 }
+                'r' => {
+                    self.colors = match &cmd[1..] {
+                        "white" => None,
+                        strategy => Some(RandomColors::try_from(strategy)?),
+                    };
+                    self.palette = None;
+                    self.color = None;
+                }
                 _ => return invalid_cmd("Strobe", cmd, COMMAND_HELP),
             };
         }
@@ -0,0 +1,158 @@
+use alloc::{vec, vec::Vec};
+use anyhow::anyhow;
+
+use crate::{
+    beat::BeatCount,
+    color::{Rgb, KELVIN_RANGE},
+    patterns::{command, invalid_cmd},
+    RENDERS_PER_SECOND,
+};
+
+use super::{LedPattern, PatternCommand};
+
+/// Linear transition of a value over a number of frames.
+struct Fade {
+    current: f32,
+    target: f32,
+    step: f32,
+}
+
+impl Fade {
+    fn new(value: f32) -> Self {
+        Self {
+            current: value,
+            target: value,
+            step: 0.0,
+        }
+    }
+
+    fn set(&mut self, target: f32, n_frames: f32) {
+        self.target = target;
+        self.step = (target - self.current).abs() / n_frames.max(1.0);
+    }
+
+    fn next(&mut self) -> f32 {
+        if self.current < self.target {
+            self.current = (self.current + self.step).min(self.target);
+        } else {
+            self.current = (self.current - self.step).max(self.target);
+        }
+
+        self.current
+    }
+}
+
+/// White light with an adjustable color temperature for ambient lighting. Changes of the
+/// temperature and the brightness fade over `fade_time` seconds.
+pub struct TunableWhite {
+    rgbs: Vec<Rgb>,
+    // the temperature fades in mired (1e6 / Kelvin), in which equal steps look equally large
+    mired: Fade,
+    brightness: Fade,
+    fade_time: f32,
+}
+
+impl TunableWhite {
+    pub fn new(n_leds: usize, kelvin: u32, brightness: u8) -> Self {
+        Self {
+            rgbs: vec![Rgb::default(); n_leds],
+            mired: Fade::new(to_mired(kelvin)),
+            brightness: Fade::new(brightness as f32),
+            fade_time: 2.0,
+        }
+    }
+
+    fn n_fade_frames(&self) -> f32 {
+        self.fade_time * RENDERS_PER_SECOND as f32
+    }
+}
+
+fn to_mired(kelvin: u32) -> f32 {
+    1_000_000.0 / kelvin as f32
+}
+
+fn parse_kelvin(arg: &str) -> anyhow::Result<u32> {
+    // the unit is optional, but like for colors at most one "K" is accepted
+    let kelvin = command::parse::<u32>(arg.strip_suffix(['K', 'k']).unwrap_or(arg))?;
+    // fails for temperatures outside of the supported range
+    Rgb::from_kelvin(kelvin)?;
+
+    Ok(kelvin)
+}
+
+impl LedPattern for TunableWhite {
+    fn next(&mut self) -> &[Rgb] {
+        let kelvin = (1_000_000.0 / self.mired.next()) as u32;
+        let kelvin = kelvin.clamp(*KELVIN_RANGE.start(), *KELVIN_RANGE.end());
+        let color = Rgb::from_kelvin(kelvin)
+            .unwrap()
+            .limited(self.brightness.next() as u8);
+
+        self.rgbs.iter_mut().for_each(|rgb| *rgb = color);
+
+        &self.rgbs
+    }
+
+    fn beat(&mut self, _beat_info: &BeatCount) {}
+
+    fn size(&self) -> usize {
+        self.rgbs.len()
+    }
+
+    // Arguments: <n_leds>,<kelvin>,<brightness>
+    fn from_str(args: &str) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let args = command::split_args(args).collect::<Vec<_>>();
+        let [n_leds, kelvin, brightness] = args[..] else {
+            return Err(anyhow!(
+                "TunableWhite needs the args <n_leds>,<kelvin>,<brightness>, got {:?}",
+                args
+            ));
+        };
+
+        Ok(Self::new(
+            command::parse(n_leds)?,
+            parse_kelvin(kelvin)?,
+            command::parse(brightness)?,
+        ))
+    }
+}
+
+static COMMAND_HELP: &str =
+    "k<kelvin> - color temperature; I<u8> - brightness; f<float> - fade time in seconds";
+
+impl PatternCommand for TunableWhite {
+    fn execute_command(&mut self, command: &str) -> anyhow::Result<()> {
+        let cmds = command::split_args(command);
+
+        for cmd in cmds {
+            if cmd.is_empty() {
+                return Err(anyhow!("Empty command given!"));
+            }
+            let set_cmd = cmd.as_bytes()[0] as char;
+
+            match set_cmd {
+                'k' => {
+                    let kelvin = parse_kelvin(&cmd[1..])?;
+                    self.mired.set(to_mired(kelvin), self.n_fade_frames());
+                }
+                'I' => {
+                    let brightness = command::parse::<u8>(&cmd[1..])?;
+                    self.brightness.set(brightness as f32, self.n_fade_frames());
+                }
+                'f' => {
+                    let fade_time = command::parse::<f32>(&cmd[1..])?;
+                    if !fade_time.is_finite() || fade_time < 0.0 {
+                        return Err(anyhow!("Fade time must be a finite number >= 0!"));
+                    }
+                    self.fade_time = fade_time;
+                }
+                _ => return invalid_cmd("TunableWhite", cmd, COMMAND_HELP),
+            };
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use crate::color::{Rgb, Rgb16};
+
+/// Gamma correction and white balance of the rendered colors.
+///
+/// The LEDs' brightness is linear to the sent values, while our eyes are much more
+/// sensitive at the low end. The gamma curve compensates for that, so that fades look
+/// smooth. The white balance scales each channel to make up for the different
+/// efficiencies of the red, green and blue LEDs.
+///
+/// The patterns hardly ever use the full range of 0..=255, but stay below their
+/// brightness limit (e.g. `MAX_INTENSITY`). A curve over the full range would squash
+/// all of their values into a few dark steps, so the curve only spans `0..=full_scale`
+/// and keeps that brightness. Values above it are passed through unchanged.
+///
+/// The results keep 8 fractional bits, which can be used by later stages.
+pub struct ColorCorrection {
+    lut: [u16; 256],
+    white_balance: Rgb, // color sent for full white
+}
+
+impl ColorCorrection {
+    pub fn new(gamma: f32, full_scale: u8, white_balance: Rgb) -> Self {
+        let mut res = Self {
+            lut: [0; 256],
+            white_balance,
+        };
+
+        res.set_gamma(gamma, full_scale);
+
+        res
+    }
+
+    pub fn set_gamma(&mut self, gamma: f32, full_scale: u8) {
+        let full_scale = full_scale.max(1) as f32;
+
+        for (i, entry) in self.lut.iter_mut().enumerate() {
+            let i = i as f32;
+            let corrected = if i < full_scale {
+                libm::powf(i / full_scale, gamma) * full_scale
+            } else {
+                i
+            };
+
+            *entry = (corrected * 256.0 + 0.5) as u16;
+        }
+    }
+
+    pub fn set_white_balance(&mut self, white_balance: Rgb) {
+        self.white_balance = white_balance;
+    }
+
+    /// Corrects a color with fractions, which are interpolated between the entries of
+    /// the curve.
+    pub fn apply(&self, rgb: &Rgb16) -> Rgb16 {
+        let channel = |c: u16, wb: u8| {
+            let (i, fraction) = ((c >> 8) as usize, (c & 0xff) as u32);
+            let low = self.lut[i] as u32;
+            let high = self.lut[(i + 1).min(255)] as u32;
+            let corrected = low + (high - low) * fraction / 256;
+
+            ((corrected * wb as u32) / 255) as u16
+        };
+
+        Rgb16 {
+            r: channel(rgb.r, self.white_balance.r),
+            g: channel(rgb.g, self.white_balance.g),
+            b: channel(rgb.b, self.white_balance.b),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use alloc::{vec, vec::Vec};
+
+use crate::color::{Rgb, Rgb16};
+
+/// Temporal dithering of the fractional part of the colors.
+///
+/// Instead of simply cutting off the fraction, it is carried over to the next frame of the
+/// same LED. E.g. a channel value of 2.25 is sent as 2, 2, 2, 3, 2, 2, 2, 3, ... At 50
+/// renders per second, this is too fast to see and looks like the value in between,
+/// which makes dim fades and gradients much smoother.
+#[derive(Default)]
+pub struct Dithering {
+    errors: Vec<[u8; 3]>, // carried fraction per LED and channel
+}
+
+impl Dithering {
+    pub fn apply(&mut self, rgbs: &[Rgb16], out: &mut [Rgb]) {
+        if self.errors.len() != rgbs.len() {
+            self.errors = vec![[0; 3]; rgbs.len()];
+        }
+
+        for ((rgb, error), o) in rgbs.iter().zip(self.errors.iter_mut()).zip(out.iter_mut()) {
+            *o = Rgb {
+                r: dither(rgb.r, &mut error[0]),
+                g: dither(rgb.g, &mut error[1]),
+                b: dither(rgb.b, &mut error[2]),
+            };
+        }
+    }
+}
+
+fn dither(value: u16, error: &mut u8) -> u8 {
+    let sum = value as u32 + *error as u32;
+    if sum >= 255 << 8 {
+        *error = 0;
+        return 255;
+    }
+
+    *error = (sum & 0xff) as u8;
+    (sum >> 8) as u8
+}
@@ -0,0 +1,104 @@
+use alloc::{vec, vec::Vec};
+use anyhow::anyhow;
+
+use crate::{color::Rgb, patterns::command::range_tuple, MAX_LEDS};
+
+/// Maps the logical LEDs the patterns render to the physical LEDs of the strips.
+///
+/// The mapping is a list of entries that describe the physical strip from its start:
+/// * `a..b`: the logical LEDs `a` to `b - 1` in order (`a` alone is only the LED `a`)
+/// * `ra..b`: the logical LEDs `a` to `b - 1` in reversed order
+/// * `sn`: `n` physical LEDs that are skipped (stay black), e.g. hidden or dead ones
+///
+/// E.g. "0..10,s2,r10..20" for a strip that is folded after 10 LEDs with 2 LEDs in the
+/// fold.
+pub struct LedMapping {
+    table: Vec<Option<usize>>, // logical index for each physical LED
+}
+
+impl TryFrom<&str> for LedMapping {
+    type Error = anyhow::Error;
+
+    fn try_from(mapping: &str) -> Result<Self, Self::Error> {
+        let mut table = vec![];
+
+        for entry in mapping.split(',') {
+            let (reversed, range) = match entry.strip_prefix('r') {
+                Some(range) => (true, range),
+                None => (false, entry),
+            };
+
+            if let Some(n_skipped) = entry.strip_prefix('s') {
+                let n_skipped = n_skipped
+                    .parse::<usize>()
+                    .map_err(|_| anyhow!("Invalid number of skipped LEDs {:?}", n_skipped))?;
+                check_len(table.len().saturating_add(n_skipped))?;
+                table.extend(core::iter::repeat(None).take(n_skipped));
+                continue;
+            }
+
+            let (remainder, (start, end)) =
+                range_tuple(range).map_err(|_| anyhow!("Invalid mapping entry {:?}", entry))?;
+            if !remainder.is_empty() || start > end {
+                return Err(anyhow!("Invalid mapping range {:?}", entry));
+            }
+            let end = if range.contains("..") {
+                end
+            } else {
+                start
+                    .checked_add(1)
+                    .ok_or_else(|| anyhow!("Invalid mapping index {:?}", entry))?
+            };
+            check_len(table.len().saturating_add((end - start) as usize))?;
+
+            let indices = (start as usize..end as usize).map(Some);
+            if reversed {
+                table.extend(indices.rev());
+            } else {
+                table.extend(indices);
+            }
+        }
+
+        Ok(Self { table })
+    }
+}
+
+impl LedMapping {
+    /// Number of physical LEDs.
+    pub fn n_leds(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Writes the physical frame for the logical frame `rgbs` into `out`.
+    pub fn apply(&self, rgbs: &[Rgb], out: &mut Vec<Rgb>) {
+        out.clear();
+        out.extend(
+            self.table
+                .iter()
+                .map(|index| index.and_then(|i| rgbs.get(i).copied()).unwrap_or_default()),
+        );
+    }
+
+    /// Adds up how often each logical LED is sent out, given how often each physical
+    /// LED is (`physical`).
+    pub fn count_copies(&self, physical: &[u16], copies: &mut [u16]) {
+        for (index, n) in self.table.iter().zip(physical) {
+            if let Some(copy) = index.and_then(|i| copies.get_mut(i)) {
+                *copy = copy.saturating_add(*n);
+            }
+        }
+    }
+}
+
+// the physical frame has to fit into the memory just like the rendered one
+fn check_len(len: usize) -> anyhow::Result<()> {
+    if len > MAX_LEDS {
+        return Err(anyhow!(
+            "The mapping has {} LEDs, the maximum is {}",
+            len,
+            MAX_LEDS
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//! Post processing of the rendered frames before they are sent to the outputs.
+
+use alloc::{format, vec::Vec};
+use anyhow::anyhow;
+
+use crate::{
+    color::{Rgb, Rgb16},
+    patterns::{
+        command::{parse, parse_rgb},
+        LedPattern,
+    },
+    util::ble::reply,
+    MAX_INTENSITY,
+};
+use correction::ColorCorrection;
+use dither::Dithering;
+use mapping::LedMapping;
+use power::{PowerLimiter, PowerModel};
+
+pub mod correction;
+pub mod dither;
+pub mod mapping;
+pub mod power;
+
+pub struct Pipeline {
+    rgbs16: Vec<Rgb16>,
+    rgbs: Vec<Rgb>,
+    physical_rgbs: Vec<Rgb>,
+    physical_copies: Vec<u16>, // how often each LED of the processed frame is sent out
+    copies: Vec<u16>,          // how often each rendered LED is sent out
+    correction: ColorCorrection,
+    power_limiter: PowerLimiter,
+    dithering: Option<Dithering>,
+    mapping: Option<LedMapping>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self {
+            rgbs16: Vec::new(),
+            rgbs: Vec::new(),
+            physical_rgbs: Vec::new(),
+            physical_copies: Vec::new(),
+            copies: Vec::new(),
+            correction: ColorCorrection::new(
+                1.0,
+                MAX_INTENSITY,
+                Rgb {
+                    r: 255,
+                    g: 255,
+                    b: 255,
+                },
+            ),
+            power_limiter: PowerLimiter::default(),
+            dithering: None,
+            mapping: None,
+        }
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renders the next frame of the pattern, runs all stages on it and returns the
+    /// processed frame. If a mapping is set, the returned frame is made of the physical
+    /// LEDs. The current draw is estimated for the parts of the processed frame sent to
+    /// the outputs (`output_ranges`).
+    pub fn process(
+        &mut self,
+        pattern: &mut dyn LedPattern,
+        output_ranges: impl Iterator<Item = (usize, usize)>,
+    ) -> &[Rgb] {
+        // the fractions of patterns rendering with a higher precision are kept for the
+        // dithering
+        self.rgbs16.resize(pattern.size(), Rgb16::default());
+        pattern.next16(&mut self.rgbs16);
+
+        for rgb in self.rgbs16.iter_mut() {
+            *rgb = self.correction.apply(rgb);
+        }
+
+        let n_sent = self.count_copies(output_ranges);
+        let copies = match self.mapping {
+            Some(_) => &self.copies,
+            None => &self.physical_copies,
+        };
+        self.power_limiter.apply(&mut self.rgbs16, copies, n_sent);
+
+        self.rgbs.resize(self.rgbs16.len(), Rgb::default());
+        if let Some(dithering) = self.dithering.as_mut() {
+            dithering.apply(&self.rgbs16, &mut self.rgbs);
+        } else {
+            for (rgb16, rgb) in self.rgbs16.iter().zip(self.rgbs.iter_mut()) {
+                *rgb = rgb16.rounded();
+            }
+        }
+
+        if let Some(mapping) = self.mapping.as_ref() {
+            mapping.apply(&self.rgbs, &mut self.physical_rgbs);
+            return &self.physical_rgbs;
+        }
+
+        &self.rgbs
+    }
+
+    // counts how often each LED is sent out by the outputs and through the mapping,
+    // returns the number of physical LEDs sent to; the ranges are cut off at the end of
+    // the processed frame like when sending
+    fn count_copies(&mut self, output_ranges: impl Iterator<Item = (usize, usize)>) -> usize {
+        let n_leds = self.rgbs16.len();
+        let frame_len = self.output_len(n_leds);
+
+        self.physical_copies.clear();
+        self.physical_copies.resize(frame_len, 0);
+        let mut n_sent = 0;
+        for (start, end) in output_ranges {
+            let end = end.min(frame_len);
+            let start = start.min(end);
+            for n in self.physical_copies[start..end].iter_mut() {
+                *n = n.saturating_add(1);
+            }
+            n_sent += end - start;
+        }
+
+        if let Some(mapping) = self.mapping.as_ref() {
+            self.copies.clear();
+            self.copies.resize(n_leds, 0);
+            mapping.count_copies(&self.physical_copies, &mut self.copies);
+        }
+
+        n_sent
+    }
+
+    /// Number of LEDs of the processed frame for a rendered frame of `n_leds` LEDs. The
+    /// ranges of the outputs refer to these LEDs.
+    pub fn output_len(&self, n_leds: usize) -> usize {
+        self.mapping.as_ref().map_or(n_leds, LedMapping::n_leds)
+    }
+
+    /// Returns true if the command (e.g. "gamma 2.2") is meant for the pipeline.
+    pub fn handles(command: &str) -> bool {
+        command
+            .split(' ')
+            .next()
+            .is_some_and(|name| COMMANDS.contains(&name))
+    }
+
+    pub fn execute_command(&mut self, command: &str) -> anyhow::Result<()> {
+        if command == "power" {
+            let (estimate, limited) = self.power_limiter.current_draw();
+            let budget = match self.power_limiter.budget {
+                Some(budget) => format!("{} mA", budget),
+                None => "unlimited".into(),
+            };
+            reply(format!(
+                "Current draw: {} mA (limited to {} mA); budget: {}",
+                estimate, limited, budget
+            ));
+            return Ok(());
+        }
+
+        match command.split_once(' ') {
+            Some(("gamma", args)) => {
+                let (gamma, full_scale) = match args.split_once(' ') {
+                    Some((gamma, full_scale)) => (gamma, parse::<u8>(full_scale)?),
+                    None => (args, MAX_INTENSITY),
+                };
+                let gamma = parse::<f32>(gamma)?;
+                if !gamma.is_finite() || gamma <= 0.0 {
+                    return Err(anyhow!("Gamma must be a finite number greater than 0!"));
+                }
+                if full_scale == 0 {
+                    return Err(anyhow!("The full scale of the gamma curve must not be 0!"));
+                }
+                self.correction.set_gamma(gamma, full_scale);
+            }
+            Some(("wb", white_balance)) => {
+                self.correction.set_white_balance(parse_rgb(white_balance)?)
+            }
+            Some(("dither", "on")) => self.dithering = Some(Dithering::default()),
+            Some(("dither", "off")) => self.dithering = None,
+            Some(("map", "off")) => self.mapping = None,
+            Some(("map", mapping)) => self.mapping = Some(LedMapping::try_from(mapping)?),
+            Some(("power", "off")) => self.power_limiter.budget = None,
+            Some(("power", budget)) if budget.starts_with("budget ") => {
+                self.power_limiter.budget = Some(parse::<u32>(&budget[7..])?);
+            }
+            Some(("power", model)) if model.starts_with("model ") => {
+                let values = model[6..]
+                    .split(',')
+                    .map(parse::<u32>)
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let [r, g, b, idle] = values[..] else {
+                    return Err(anyhow!("The power model needs exactly 4 values!"));
+                };
+                self.power_limiter.model = PowerModel { r, g, b, idle };
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Invalid pipeline command {:?}; Available commands are: {}",
+                    command,
+                    COMMAND_HELP
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+static COMMANDS: [&str; 5] = ["gamma", "wb", "dither", "power", "map"];
+
+static COMMAND_HELP: &str =
+    "gamma <float>[ <full scale>] - gamma curve (1.0 is linear) over 0..=full scale (default: the patterns' brightness limit); wb <rgb> - color of full white; dither [on,off] - temporal dithering; power - report current draw; power budget <mA> - limit current draw; power off - no limit; power model <r>,<g>,<b>,<idle> - mA per channel and LED; map <a..b,ra..b,sn,...> - map logical to physical LEDs (forward, reversed, skipped); map off - no mapping";
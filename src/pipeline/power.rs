@@ -0,0 +1,87 @@
+use crate::color::Rgb16;
+
+/// Estimated current draw of a single LED in milliamps.
+///
+/// * `r`, `g`, `b`: current of each channel at full brightness
+/// * `idle`: current of the chip itself, even if the LED is off
+#[derive(Copy, Clone, Debug)]
+pub struct PowerModel {
+    pub r: u32,
+    pub g: u32,
+    pub b: u32,
+    pub idle: u32,
+}
+
+impl Default for PowerModel {
+    // typical values for WS2812B
+    fn default() -> Self {
+        Self {
+            r: 20,
+            g: 20,
+            b: 20,
+            idle: 1,
+        }
+    }
+}
+
+/// Limits the estimated current draw of a frame to a budget (e.g. what the power supply
+/// can deliver) by scaling down all colors proportionally.
+///
+/// The estimate is made for what is actually sent out: an LED of the frame shown by
+/// several outputs (or several times by a mapping) draws current for each copy.
+#[derive(Default)]
+pub struct PowerLimiter {
+    pub model: PowerModel,
+    pub budget: Option<u32>, // in mA; None means unlimited
+    estimate: u32,           // draw of the last frame before limiting in mA
+    limited: u32,            // draw of the last frame after limiting in mA
+}
+
+// full brightness of a channel including the fractional bits
+const FULL: u64 = 255 << 8;
+
+impl PowerLimiter {
+    /// Estimates and limits the draw of the frame, `copies` tells how often each of its
+    /// LEDs is sent out and `n_sent` is the number of physical LEDs sent to (including
+    /// the ones that stay black).
+    pub fn apply(&mut self, rgbs: &mut [Rgb16], copies: &[u16], n_sent: usize) {
+        let idle = self.model.idle * n_sent as u32;
+        let channels = rgbs
+            .iter()
+            .zip(copies)
+            .map(|(rgb, n)| {
+                (rgb.r as u64 * self.model.r as u64
+                    + rgb.g as u64 * self.model.g as u64
+                    + rgb.b as u64 * self.model.b as u64)
+                    * *n as u64
+            })
+            .sum::<u64>()
+            / FULL;
+
+        self.estimate = idle + channels as u32;
+        self.limited = self.estimate;
+
+        let Some(budget) = self.budget else {
+            return;
+        };
+        if self.estimate <= budget || channels == 0 {
+            return;
+        }
+
+        // the idle current can't be reduced, so only the remaining budget can be used
+        // for the channels
+        let scale = ((budget.saturating_sub(idle) as u64) << 16) / channels;
+        for rgb in rgbs.iter_mut() {
+            rgb.r = ((rgb.r as u64 * scale) >> 16) as u16;
+            rgb.g = ((rgb.g as u64 * scale) >> 16) as u16;
+            rgb.b = ((rgb.b as u64 * scale) >> 16) as u16;
+        }
+
+        self.limited = idle + ((channels * scale) >> 16) as u32;
+    }
+
+    /// Estimated current draw of the last frame before and after limiting in mA.
+    pub fn current_draw(&self) -> (u32, u32) {
+        (self.estimate, self.limited)
+    }
+}
@@ -0,0 +1,324 @@
+//! Encoding of colors into RMT pulse codes.
+//!
+//! Nothing in here touches the hardware, so the exact bit timings can be checked on any
+//! host. The produced `u32` entries have the same layout as the ESP32's RMT memory:
+//! bits 0-14: length1; bit 15: level1; bits 16-30: length2; bit 31: level2.
+
+use anyhow::anyhow;
+
+use crate::color::Rgb;
+
+// the RMT is clocked with 80 MHz and no divider
+const RMT_TICKS_PER_US: u32 = 80;
+
+// enough room for strips with a fourth (white) channel
+const MAX_CHANNELS: usize = 4;
+
+/// Order in which the LED chips of a strip expect their color channels.
+/// Variants ending with `W` are RGBW strips (e.g. SK6812) with a dedicated white LED.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ColorOrder {
+    Rgb,
+    Rbg,
+    #[default]
+    Grb,
+    Gbr,
+    Brg,
+    Bgr,
+    Rgbw,
+    Grbw,
+}
+
+impl ColorOrder {
+    pub fn n_channels(&self) -> usize {
+        match self {
+            Self::Rgbw | Self::Grbw => 4,
+            _ => 3,
+        }
+    }
+
+    /// Returns the channel values of the given color in the order they have to be sent.
+    /// Only the first `n_channels()` entries are meaningful. `white` is the color of the
+    /// white LED of RGBW strips.
+    pub fn components(&self, rgb: &Rgb, white: &Rgb) -> [u8; MAX_CHANNELS] {
+        match self {
+            Self::Rgb => [rgb.r, rgb.g, rgb.b, 0],
+            Self::Rbg => [rgb.r, rgb.b, rgb.g, 0],
+            Self::Grb => [rgb.g, rgb.r, rgb.b, 0],
+            Self::Gbr => [rgb.g, rgb.b, rgb.r, 0],
+            Self::Brg => [rgb.b, rgb.r, rgb.g, 0],
+            Self::Bgr => [rgb.b, rgb.g, rgb.r, 0],
+            Self::Rgbw => {
+                let c = rgb.to_rgbw(white);
+                [c.r, c.g, c.b, c.w]
+            }
+            Self::Grbw => {
+                let c = rgb.to_rgbw(white);
+                [c.g, c.r, c.b, c.w]
+            }
+        }
+    }
+}
+
+impl TryFrom<&str> for ColorOrder {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "rgb" => Ok(Self::Rgb),
+            "rbg" => Ok(Self::Rbg),
+            "grb" => Ok(Self::Grb),
+            "gbr" => Ok(Self::Gbr),
+            "brg" => Ok(Self::Brg),
+            "bgr" => Ok(Self::Bgr),
+            "rgbw" => Ok(Self::Rgbw),
+            "grbw" => Ok(Self::Grbw),
+            c => Err(anyhow!(
+                "Invalid color order {:?}. Available orders are: rgb, rbg, grb, gbr, brg, bgr, rgbw, grbw",
+                c
+            )),
+        }
+    }
+}
+
+/// Supported LED chips, each one with its own bit timings.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum LedChip {
+    /// WS2811 driver ICs in their 400 kHz (low speed) mode
+    Ws2811,
+    #[default]
+    Ws2812b,
+    /// WS2813 and WS2815, which share the same protocol
+    Ws2813,
+    Sk6812,
+}
+
+impl LedChip {
+    pub fn timing(&self) -> Timing {
+        match self {
+            Self::Ws2811 => Timing::from_ns(500, 2000, 1200, 1300, 50_000),
+            // Timings inspired by
+            // https://wp.josh.com/2014/05/13/ws2812-neopixels-are-not-so-finicky-once-you-get-to-know-them/
+            Self::Ws2812b => Timing::from_ns(350, 600, 700, 600, 6_000),
+            Self::Ws2813 => Timing::from_ns(300, 800, 750, 300, 280_000),
+            Self::Sk6812 => Timing::from_ns(300, 900, 600, 600, 80_000),
+        }
+    }
+}
+
+impl TryFrom<&str> for LedChip {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "ws2811" => Ok(Self::Ws2811),
+            "ws2812" | "ws2812b" => Ok(Self::Ws2812b),
+            "ws2813" | "ws2815" => Ok(Self::Ws2813),
+            "sk6812" => Ok(Self::Sk6812),
+            c => Err(anyhow!(
+                "Invalid LED chip {:?}. Available chips are: ws2811, ws2812b, ws2813, ws2815, sk6812",
+                c
+            )),
+        }
+    }
+}
+
+/// Pulse lengths of a chip's protocol in RMT clock ticks.
+///
+/// * `t0_high`, `t0_low`: high and low time when sending a 0 bit
+/// * `t1_high`, `t1_low`: high and low time when sending a 1 bit
+/// * `reset`: low time after a frame, so that the chips latch the received data
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Timing {
+    pub t0_high: u16,
+    pub t0_low: u16,
+    pub t1_high: u16,
+    pub t1_low: u16,
+    pub reset: u16,
+}
+
+impl Timing {
+    pub const fn from_ns(t0_high: u32, t0_low: u32, t1_high: u32, t1_low: u32, reset: u32) -> Self {
+        Self {
+            t0_high: ns_to_ticks(t0_high),
+            t0_low: ns_to_ticks(t0_low),
+            t1_high: ns_to_ticks(t1_high),
+            t1_low: ns_to_ticks(t1_low),
+            reset: ns_to_ticks(reset),
+        }
+    }
+
+    pub fn bit_code(&self, bit_is_high: bool) -> u32 {
+        if bit_is_high {
+            pulse_code(true, self.t1_high, false, self.t1_low)
+        } else {
+            pulse_code(true, self.t0_high, false, self.t0_low)
+        }
+    }
+
+    /// Keeps the line low for the reset time and then stops the transmission with a
+    /// zero-length second pulse.
+    pub fn reset_code(&self) -> u32 {
+        pulse_code(false, self.reset, false, 0)
+    }
+}
+
+const fn ns_to_ticks(ns: u32) -> u16 {
+    (ns * RMT_TICKS_PER_US / 1000) as u16
+}
+
+pub const fn pulse_code(level1: bool, length1: u16, level2: bool, length2: u16) -> u32 {
+    ((level2 as u32) << 31)
+        | ((length2 as u32 & 0x7fff) << 16)
+        | ((level1 as u32) << 15)
+        | (length1 as u32 & 0x7fff)
+}
+
+/// Number of pulse codes needed for `n_leds` LEDs including the final reset code.
+pub fn n_codes(n_leds: usize, order: ColorOrder) -> usize {
+    n_leds * order.n_channels() * 8 + 1
+}
+
+/// Writes the codes `first..first + out.len()` of a frame's pulse code sequence into
+/// `out`. The sequence consists of each channel's bits, most significant bit first, for
+/// every LED followed by the final reset code. Returns the number of written codes, which
+/// is smaller than `out.len()` when the end of the sequence is reached.
+///
+/// This way, the codes can be produced chunk by chunk while the frame is being sent
+/// without ever holding the whole encoded frame in memory. It runs while the other half
+/// of the channel memory is being sent, so it only does the color conversion once per
+/// LED instead of once per bit.
+pub fn encode_part(
+    rgbs: &[Rgb],
+    order: ColorOrder,
+    white: &Rgb,
+    timing: &Timing,
+    first: usize,
+    out: &mut [u32],
+) -> usize {
+    let codes_per_led = order.n_channels() * 8;
+    let last = n_codes(rgbs.len(), order) - 1;
+    let end = (first + out.len()).min(last + 1);
+    let bit_codes = [timing.bit_code(false), timing.bit_code(true)];
+
+    let mut i = first;
+    while i < end {
+        if i == last {
+            out[i - first] = timing.reset_code();
+            break;
+        }
+
+        // all bits of the LED that are part of this chunk
+        let led = i / codes_per_led;
+        let components = order.components(&rgbs[led], white);
+        let led_end = ((led + 1) * codes_per_led).min(end);
+        for (bit, code) in (i % codes_per_led..).zip(out[i - first..led_end - first].iter_mut()) {
+            *code = bit_codes[((components[bit / 8] >> (7 - bit % 8)) & 0b1) as usize];
+        }
+
+        i = led_end;
+    }
+
+    end.saturating_sub(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::WHITE;
+
+    const CHIPS: [LedChip; 4] = [
+        LedChip::Ws2811,
+        LedChip::Ws2812b,
+        LedChip::Ws2813,
+        LedChip::Sk6812,
+    ];
+
+    fn ticks(t0_high: u16, t0_low: u16, t1_high: u16, t1_low: u16, reset: u16) -> Timing {
+        Timing {
+            t0_high,
+            t0_low,
+            t1_high,
+            t1_low,
+            reset,
+        }
+    }
+
+    #[test]
+    fn chip_timings_in_rmt_ticks() {
+        assert_eq!(LedChip::Ws2811.timing(), ticks(40, 160, 96, 104, 4000));
+        assert_eq!(LedChip::Ws2812b.timing(), ticks(28, 48, 56, 48, 480));
+        assert_eq!(LedChip::Ws2813.timing(), ticks(24, 64, 60, 24, 22400));
+        assert_eq!(LedChip::Sk6812.timing(), ticks(24, 72, 48, 48, 6400));
+    }
+
+    #[test]
+    fn bit_codes_are_high_then_low() {
+        for chip in CHIPS {
+            let t = chip.timing();
+            let zero = t.bit_code(false);
+            let one = t.bit_code(true);
+
+            assert_eq!(zero, (t.t0_low as u32) << 16 | 1 << 15 | t.t0_high as u32);
+            assert_eq!(one, (t.t1_low as u32) << 16 | 1 << 15 | t.t1_high as u32);
+            assert_eq!(t.reset_code(), t.reset as u32);
+        }
+    }
+
+    #[test]
+    fn channels_are_sent_msb_first_in_color_order() {
+        let rgbs = [Rgb {
+            r: 0x80,
+            g: 0x01,
+            b: 0xff,
+        }];
+        // green, red, blue
+        let bits = "00000001".to_owned() + "10000000" + "11111111";
+
+        for chip in CHIPS {
+            let t = chip.timing();
+            let mut codes = [0; 25];
+            let n = encode_part(&rgbs, ColorOrder::Grb, &WHITE, &t, 0, &mut codes);
+
+            assert_eq!(n, n_codes(1, ColorOrder::Grb));
+            for (code, bit) in codes.iter().zip(bits.chars()) {
+                assert_eq!(*code, t.bit_code(bit == '1'));
+            }
+            assert_eq!(codes[24], t.reset_code());
+        }
+    }
+
+    #[test]
+    fn chunked_encoding_matches_full_encoding() {
+        let rgbs = (0..7)
+            .map(|i| Rgb {
+                r: i * 37,
+                g: 255 - i * 11,
+                b: i << 5,
+            })
+            .collect::<Vec<_>>();
+
+        for order in [ColorOrder::Grb, ColorOrder::Grbw] {
+            let t = LedChip::Sk6812.timing();
+            let mut full = vec![0; n_codes(rgbs.len(), order)];
+            let n = encode_part(&rgbs, order, &WHITE, &t, 0, &mut full);
+            assert_eq!(n, full.len());
+
+            // chunks of half the RMT channel memory, like the refills of a transmission,
+            // and chunks that split the LEDs at odd positions
+            for chunk_len in [32, 7] {
+                let mut chunked = vec![];
+                let mut chunk = vec![0; chunk_len];
+                loop {
+                    let n = encode_part(&rgbs, order, &WHITE, &t, chunked.len(), &mut chunk);
+                    chunked.extend_from_slice(&chunk[..n]);
+                    if n < chunk.len() {
+                        break;
+                    }
+                }
+
+                assert_eq!(chunked, full);
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+use anyhow::anyhow;
+#[cfg(not(feature = "apa102"))]
+use esp_hal::{
+    clock::Clocks,
+    peripheral::Peripheral,
+    peripherals::RMT,
+    rmt::{Rmt, TxChannelConfig},
+    Blocking,
+};
+#[cfg(not(feature = "apa102"))]
+use fugit::HertzU32;
+
+use crate::color::Rgb;
+#[cfg(not(feature = "apa102"))]
+use crate::color::WHITE;
+
+pub use encoding::ColorOrder;
+#[cfg(not(feature = "apa102"))]
+pub use encoding::LedChip;
+pub use yalbir::transmit::encoding;
+
+// the RMT drives WS2812 like strips, which aren't used in the APA102 build
+#[cfg(not(feature = "apa102"))]
+pub mod rmt;
+#[cfg(feature = "apa102")]
+pub mod spi;
+
+/// A physical output (e.g. a strip connected to a pin) that rendered frames are sent to.
+///
+/// Sending is split into `start()` and `poll()`, so that multiple outputs can transmit at
+/// the same time.
+pub trait LedOutput: Send {
+    // begin sending out the given colors
+    fn start(&mut self, rgbs: &[Rgb]);
+
+    // continue a started transmission of the same colors given to start();
+    // returns true as soon as it is finished
+    fn poll(&mut self, rgbs: &[Rgb]) -> bool;
+
+    // change settings of the output at runtime
+    fn execute_command(&mut self, command: &str) -> anyhow::Result<()>;
+}
+
+/// Settings describing a WS2812 like strip connected to an output.
+#[cfg(not(feature = "apa102"))]
+#[derive(Copy, Clone, Debug)]
+pub struct StripConfig {
+    pub order: ColorOrder,
+    pub chip: LedChip,
+    pub white: Rgb, // color of the white LEDs of RGBW strips
+}
+
+#[cfg(not(feature = "apa102"))]
+impl StripConfig {
+    pub const fn new(order: ColorOrder, chip: LedChip) -> Self {
+        Self {
+            order,
+            chip,
+            white: WHITE,
+        }
+    }
+}
+
+// Output, range of the frame shown by the output
+type OutputWithRange = (Box<dyn LedOutput>, (usize, usize));
+
+/// All outputs of the controller. A frame is split up between them by their ranges and
+/// sent out on all of them concurrently.
+#[derive(Default)]
+pub struct Outputs {
+    outputs: Vec<OutputWithRange>,
+}
+
+impl Outputs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an output showing the LEDs `range.0..range.1` of each frame.
+    pub fn add(&mut self, output: Box<dyn LedOutput>, range: (usize, usize)) {
+        self.outputs.push((output, range));
+    }
+
+    /// The parts of the frame shown by the outputs.
+    pub fn ranges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.outputs.iter().map(|(_, range)| *range)
+    }
+
+    /// Changes the part of the frame shown by the output with the given index.
+    pub fn set_range(
+        &mut self,
+        index: usize,
+        range: (usize, usize),
+        frame_len: usize,
+    ) -> anyhow::Result<()> {
+        if range.0 > range.1 || range.1 > frame_len {
+            return Err(anyhow!(
+                "Invalid output range {}..{} for a frame of {} LEDs",
+                range.0,
+                range.1,
+                frame_len
+            ));
+        }
+
+        let (_, output_range) = self
+            .outputs
+            .get_mut(index)
+            .ok_or_else(|| anyhow!("Output index out of range {}", index))?;
+        *output_range = range;
+
+        Ok(())
+    }
+
+    /// Adapts the outputs to a new frame length: outputs that showed the frame up until
+    /// its end keep doing so. Others are cut off at the end of the frame while sending.
+    pub fn resize(&mut self, old_len: usize, new_len: usize) {
+        for (_, range) in self.outputs.iter_mut() {
+            if range.1 == old_len {
+                range.1 = new_len;
+            }
+        }
+    }
+
+    /// Sends the frame to all outputs and blocks until every transmission is finished.
+    pub fn send(&mut self, frame: &[Rgb]) {
+        for (output, range) in self.outputs.iter_mut() {
+            output.start(slice_of(frame, *range));
+        }
+
+        let mut is_sending = vec![true; self.outputs.len()];
+        while is_sending.iter().any(|s| *s) {
+            for ((output, range), sending) in self.outputs.iter_mut().zip(is_sending.iter_mut()) {
+                if *sending {
+                    *sending = !output.poll(slice_of(frame, *range));
+                }
+            }
+        }
+    }
+
+    /// Forwards the command to the output with the given index or to all outputs if no
+    /// index is given.
+    pub fn execute_command(&mut self, index: Option<usize>, command: &str) -> anyhow::Result<()> {
+        if let Some(index) = index {
+            let (output, _) = self
+                .outputs
+                .get_mut(index)
+                .ok_or_else(|| anyhow!("Output index out of range {}", index))?;
+            output.execute_command(command)
+        } else {
+            self.outputs
+                .iter_mut()
+                .try_for_each(|(output, _)| output.execute_command(command))
+        }
+    }
+}
+
+// part of the frame inside the range, cut off at the end of the frame
+fn slice_of(frame: &[Rgb], range: (usize, usize)) -> &[Rgb] {
+    let end = range.1.min(frame.len());
+    &frame[range.0.min(end)..end]
+}
+
+#[cfg(not(feature = "apa102"))]
+pub fn init_rmt<'d>(rmt: impl Peripheral<P = RMT> + 'd, clocks: &Clocks) -> Rmt<'d, Blocking> {
+    Rmt::new(rmt, HertzU32::MHz(80), clocks, None).unwrap()
+}
+
+/// Channel configuration used for every LED strip output. Has to be used with the
+/// channel creators of the RMT returned by `init_rmt()`.
+#[cfg(not(feature = "apa102"))]
+pub fn tx_channel_config() -> TxChannelConfig {
+    TxChannelConfig {
+        clk_divider: 1,
+        idle_output_level: false,
+        idle_output: false,
+        carrier_modulation: false,
+        carrier_high: 1,
+        carrier_low: 1,
+        carrier_level: false,
+    }
+}
@@ -0,0 +1,171 @@
+//! Driving WS2812 like strips via the RMT peripheral.
+//!
+//! The RMT of the ESP32 only has 64 words of memory per channel, so longer transmissions
+//! have to be refilled while they are running. esp-hal does that in
+//! `SingleShotTxTransaction::wait()`, which only serves one channel at a time and needs
+//! the whole transmission encoded up front (4 bytes per bit, so about 100 bytes per LED).
+//! Here, the refilling happens in `poll()` instead, so that all outputs can be started
+//! first and then be served in turns until every one of them is done. Each refill encodes
+//! the next half of the channel memory straight from the colors (wrap-around mode), so
+//! the strip length is only limited by the memory of the frame itself.
+
+use alloc::boxed::Box;
+use anyhow::anyhow;
+use esp_hal::{
+    rmt::{private::TxChannelInternal, Channel},
+    Blocking,
+};
+
+use super::{
+    encoding::{self, Timing},
+    ColorOrder, LedChip, LedOutput, StripConfig,
+};
+use crate::{color::Rgb, patterns::command::parse_rgb};
+
+const RMT_RAM_START: usize = 0x3ff5_6800;
+const RMT_CHANNEL_RAM_SIZE: usize = 64;
+
+/// Object safe access to a configured RMT TX channel, so that channels with different
+/// channel numbers can be stored side by side.
+trait RmtTx: Send {
+    /// Writes the first chunk of `codes` into the channel memory and starts sending.
+    /// Returns the number of written codes.
+    fn start(&self, codes: &[u32]) -> usize;
+
+    /// Returns true (and resets the flag) if half of the channel memory has been sent.
+    fn threshold_reached(&self) -> bool;
+
+    fn is_done(&self) -> bool;
+
+    fn is_error(&self) -> bool;
+
+    fn ram_start(&self) -> *mut u32;
+}
+
+impl<const CH: u8> RmtTx for Channel<Blocking, CH>
+where
+    Channel<Blocking, CH>: TxChannelInternal<Blocking> + Send,
+{
+    fn start(&self, codes: &[u32]) -> usize {
+        <Self as TxChannelInternal<Blocking>>::send_raw(codes, false, 0)
+    }
+
+    fn threshold_reached(&self) -> bool {
+        let reached = <Self as TxChannelInternal<Blocking>>::is_threshold_set();
+        if reached {
+            <Self as TxChannelInternal<Blocking>>::reset_threshold_set();
+        }
+
+        reached
+    }
+
+    fn is_done(&self) -> bool {
+        <Self as TxChannelInternal<Blocking>>::is_done()
+    }
+
+    fn is_error(&self) -> bool {
+        <Self as TxChannelInternal<Blocking>>::is_error()
+    }
+
+    fn ram_start(&self) -> *mut u32 {
+        (RMT_RAM_START + CH as usize * RMT_CHANNEL_RAM_SIZE * 4) as *mut u32
+    }
+}
+
+/// A WS2812 like strip connected to one RMT channel.
+pub struct RmtOutput {
+    channel: Box<dyn RmtTx>,
+    config: StripConfig,
+    index: usize,   // next code to be written into the channel memory
+    timing: Timing, // of the chip, fixed for the running transmission
+}
+
+impl RmtOutput {
+    /// Creates the output on an already configured channel.
+    pub fn new<const CH: u8>(channel: Channel<Blocking, CH>, config: StripConfig) -> Self
+    where
+        Channel<Blocking, CH>: TxChannelInternal<Blocking> + Send + 'static,
+    {
+        Self {
+            channel: Box::new(channel),
+            config,
+            index: 0,
+            timing: config.chip.timing(),
+        }
+    }
+
+    // refills the half of the channel memory that has just been sent
+    fn refill(&mut self, rgbs: &[Rgb]) {
+        if self.index >= encoding::n_codes(rgbs.len(), self.config.order)
+            || !self.channel.threshold_reached()
+        {
+            return;
+        }
+
+        let mut codes = [0; RMT_CHANNEL_RAM_SIZE / 2];
+        let n = encoding::encode_part(
+            rgbs,
+            self.config.order,
+            &self.config.white,
+            &self.timing,
+            self.index,
+            &mut codes,
+        );
+
+        let ram_index = (((self.index - RMT_CHANNEL_RAM_SIZE) / (RMT_CHANNEL_RAM_SIZE / 2)) % 2)
+            * (RMT_CHANNEL_RAM_SIZE / 2);
+        let ptr = unsafe { self.channel.ram_start().add(ram_index) };
+
+        for (i, code) in codes[..n].iter().enumerate() {
+            unsafe { ptr.add(i).write_volatile(*code) };
+        }
+
+        self.index += RMT_CHANNEL_RAM_SIZE / 2;
+    }
+}
+
+impl LedOutput for RmtOutput {
+    fn start(&mut self, rgbs: &[Rgb]) {
+        // the refills have to be fast, so they reuse the timing of the transmission
+        self.timing = self.config.chip.timing();
+
+        let mut codes = [0; RMT_CHANNEL_RAM_SIZE];
+        let n = encoding::encode_part(
+            rgbs,
+            self.config.order,
+            &self.config.white,
+            &self.timing,
+            0,
+            &mut codes,
+        );
+
+        self.index = self.channel.start(&codes[..n]);
+    }
+
+    fn poll(&mut self, rgbs: &[Rgb]) -> bool {
+        if self.channel.is_error() {
+            log::info!("Transmission error on RMT output!");
+            return true;
+        }
+
+        self.refill(rgbs);
+
+        self.index >= encoding::n_codes(rgbs.len(), self.config.order) && self.channel.is_done()
+    }
+
+    fn execute_command(&mut self, command: &str) -> anyhow::Result<()> {
+        match command.split_once(' ') {
+            Some(("order", order)) => self.config.order = ColorOrder::try_from(order)?,
+            Some(("chip", chip)) => self.config.chip = LedChip::try_from(chip)?,
+            Some(("white", white)) => self.config.white = parse_rgb(white)?,
+            _ => {
+                return Err(anyhow!(
+                    "Invalid command {:?} for RMT output; Available commands are: order <order>; chip <chip>; white <color>",
+                    command
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! Driving clocked APA102 / SK9822 strips via SPI.
+//!
+//! Each LED frame starts with a 5-bit global brightness field. Instead of always sending
+//! full brightness, the smallest brightness that can still show the brightest channel is
+//! chosen, which gives a much finer resolution for dim colors.
+
+use alloc::vec::Vec;
+use anyhow::anyhow;
+use esp_hal::spi::{
+    master::{Instance, Spi},
+    FullDuplexMode,
+};
+
+use super::{ColorOrder, LedOutput};
+use crate::color::{Rgb, WHITE};
+
+const MAX_BRIGHTNESS: u32 = 31;
+
+pub struct Apa102Output<'d, T> {
+    spi: Spi<'d, T, FullDuplexMode>,
+    order: ColorOrder,
+    bytes: Vec<u8>,
+}
+
+impl<'d, T> Apa102Output<'d, T>
+where
+    T: Instance,
+{
+    /// Creates the output on an SPI bus with configured SCK and MOSI pins. APA102 strips
+    /// expect the order blue, green, red, which is also the default of most SK9822 strips.
+    pub fn new(spi: Spi<'d, T, FullDuplexMode>) -> Self {
+        Self {
+            spi,
+            order: ColorOrder::Bgr,
+            bytes: Vec::new(),
+        }
+    }
+}
+
+/// Returns the LED frame of a color: the global brightness byte followed by the channels
+/// in the given order, scaled up to compensate for the reduced brightness.
+fn led_frame(rgb: &Rgb, order: ColorOrder) -> [u8; 4] {
+    let max = rgb.r.max(rgb.g).max(rgb.b) as u32;
+    if max == 0 {
+        return [0b1110_0000, 0, 0, 0];
+    }
+
+    // smallest brightness at which the brightest channel still fits into 8 bits
+    let brightness = (max * MAX_BRIGHTNESS).div_ceil(255);
+    let scale = |c: u8| ((c as u32 * MAX_BRIGHTNESS + brightness / 2) / brightness).min(255) as u8;
+
+    let scaled = Rgb {
+        r: scale(rgb.r),
+        g: scale(rgb.g),
+        b: scale(rgb.b),
+    };
+    let components = order.components(&scaled, &WHITE);
+
+    [
+        0b1110_0000 | brightness as u8,
+        components[0],
+        components[1],
+        components[2],
+    ]
+}
+
+impl<'d, T> LedOutput for Apa102Output<'d, T>
+where
+    T: Instance + Send,
+{
+    fn start(&mut self, rgbs: &[Rgb]) {
+        self.bytes.clear();
+
+        // start frame
+        self.bytes.extend_from_slice(&[0; 4]);
+
+        for rgb in rgbs {
+            self.bytes.extend_from_slice(&led_frame(rgb, self.order));
+        }
+
+        // SK9822 needs a reset frame to latch the data, then every LED needs half a clock
+        // cycle to pass the data on, so some more clock cycles are added at the end
+        self.bytes.extend_from_slice(&[0; 4]);
+        self.bytes
+            .extend(core::iter::repeat(0).take(rgbs.len().div_ceil(16)));
+
+        if let Err(err) = self.spi.write_bytes(&self.bytes) {
+            log::info!("SPI transmission error: {:?}", err);
+        }
+    }
+
+    fn poll(&mut self, _rgbs: &[Rgb]) -> bool {
+        // the transmission in start() is blocking
+        true
+    }
+
+    fn execute_command(&mut self, command: &str) -> anyhow::Result<()> {
+        match command.split_once(' ') {
+            Some(("order", order)) => {
+                let order = ColorOrder::try_from(order)?;
+                if order.n_channels() != 3 {
+                    return Err(anyhow!("APA102 strips have no white channel!"));
+                }
+                self.order = order;
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Invalid command {:?} for APA102 output; Available commands are: order <order>",
+                    command
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
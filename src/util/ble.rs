@@ -18,6 +18,12 @@ const INFO_PACKET_LENGTH: usize = (bleps::attribute_server::MTU - 3) as usize;
 static COMMAND_REPLY: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 static mut REPLY: String = String::new();
 
+/// Sends the message as a notification to the connected client.
+pub fn reply(message: String) {
+    unsafe { REPLY = message };
+    COMMAND_REPLY.signal(())
+}
+
 #[embassy_executor::task]
 pub(crate) async fn ble_handling(mut ble: Ble<BleConnector<'static>>) {
     loop {
@@ -43,8 +49,7 @@ pub(crate) async fn ble_handling(mut ble: Ble<BleConnector<'static>>) {
             log::info!("RECEIVED: Offset {}, data {:?}", offset, data);
             let res = handle_wireless_input(core::str::from_utf8(data).unwrap());
             if let Err(err) = res {
-                unsafe { REPLY = err.to_string() };
-                COMMAND_REPLY.signal(())
+                reply(err.to_string());
             }
         };
 
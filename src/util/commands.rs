@@ -1,22 +1,255 @@
-use crate::{beat::tapping::beat_input, patterns::PatternCommand, SHARED};
+use alloc::format;
+use anyhow::anyhow;
+
+use crate::{
+    beat::{
+        tapping::{beat_input, bpm_input, downbeat_input, nudge_input, TapInfo},
+        Swing, TimeSignature,
+    },
+    patterns::{
+        command::{parse, parse_tuple},
+        LedPattern, PatternCommand,
+    },
+    pipeline::Pipeline,
+    util::{ble::reply, random::set_seed},
+    MAX_LEDS, SHARED,
+};
+
+// runs the given function on the tap info, fails if there is no tempo yet
+fn with_tempo<R>(f: impl FnOnce(&mut TapInfo) -> anyhow::Result<R>) -> anyhow::Result<R> {
+    critical_section::with(|cs| {
+        let mut shared = SHARED.borrow_ref_mut(cs);
+        let tap_info = shared
+            .tap_info
+            .as_mut()
+            .filter(|info| info.interval.is_some())
+            .ok_or_else(|| anyhow!("No tempo yet! Tap the beat or use \"bpm <float>\" first"))?;
+
+        f(tap_info)
+    })
+}
+
+// multiplies the rate on top of the tapped tempo, the tempo itself stays untouched
+fn change_rate(factor: f32) -> anyhow::Result<()> {
+    with_tempo(|tap_info| tap_info.set_rate(tap_info.rate * factor))
+}
+
+// "rate <float>" sets the rate multiplier, "rate reset" goes back to the tapped tempo
+fn rate_command(arg: &str) -> anyhow::Result<()> {
+    let rate = match arg {
+        "reset" => 1.0,
+        rate => parse::<f32>(rate)?,
+    };
+
+    with_tempo(|tap_info| tap_info.set_rate(rate))
+}
+
+// forwards a setting to the output given as first argument (e.g. "order 1 grb") or to
+// all outputs if no index is given (e.g. "order grb")
+fn configure_outputs(setting: &str, args: &str) -> anyhow::Result<()> {
+    let (index, arg) = match args.split_once(' ') {
+        Some((index, arg)) => (Some(parse::<usize>(index)?), arg),
+        None => (None, args),
+    };
+
+    critical_section::with(|cs| {
+        SHARED
+            .borrow_ref_mut(cs)
+            .outputs
+            .as_mut()
+            .unwrap()
+            .execute_command(index, &format!("{} {}", setting, arg))
+    })
+}
+
+// "range <index> <a..b>" lets the output with the given index show the LEDs a..b of the
+// frame, so that every strip can show its own part
+fn set_output_range(args: &str) -> anyhow::Result<()> {
+    let (index, range) = args
+        .split_once(' ')
+        .ok_or_else(|| anyhow!("Missing output index! Use \"range <index> <a..b>\""))?;
+    let index = parse::<usize>(index)?;
+    let range = parse_tuple::<usize>(range)?;
 
-fn change_speed(factor: f32) {
     critical_section::with(|cs| {
         let mut shared = SHARED.borrow_ref_mut(cs);
-        let tap_info = shared.tap_info.as_mut().unwrap();
-        tap_info.interval = Some((tap_info.interval.unwrap() as f32 * 1f32 / factor) as u64);
+        let shared = &mut *shared;
+
+        let n_leds = shared.rgbs.as_ref().unwrap().size();
+        let frame_len = shared.pipeline.as_ref().unwrap().output_len(n_leds);
+        shared
+            .outputs
+            .as_mut()
+            .unwrap()
+            .set_range(index, range, frame_len)
+    })
+}
+
+// changes the total number of LEDs of the frame and of the outputs showing it
+fn set_n_leds(arg: &str) -> anyhow::Result<()> {
+    let n_leds = parse::<usize>(arg)?;
+    if n_leds > MAX_LEDS {
+        return Err(anyhow!(
+            "{} LEDs don't fit into the memory, the maximum is {}",
+            n_leds,
+            MAX_LEDS
+        ));
+    }
+
+    critical_section::with(|cs| {
+        let mut shared = SHARED.borrow_ref_mut(cs);
+        let shared = &mut *shared;
+
+        let rgbs = shared.rgbs.as_mut().unwrap();
+        let old_n_leds = rgbs.size();
+        rgbs.resize(n_leds)?;
+
+        // with a mapping, the outputs are sent the physical LEDs, whose number stays
+        let pipeline = shared.pipeline.as_ref().unwrap();
+        shared
+            .outputs
+            .as_mut()
+            .unwrap()
+            .resize(pipeline.output_len(old_n_leds), pipeline.output_len(n_leds));
+
+        Ok(())
+    })
+}
+
+// runs a command of the render pipeline; a new mapping changes the length of the frame
+// sent to the outputs, which are adapted to it like to a new number of LEDs
+fn configure_pipeline(command: &str) -> anyhow::Result<()> {
+    critical_section::with(|cs| {
+        let mut shared = SHARED.borrow_ref_mut(cs);
+        let shared = &mut *shared;
+
+        let n_leds = shared.rgbs.as_ref().unwrap().size();
+        let pipeline = shared.pipeline.as_mut().unwrap();
+        let old_len = pipeline.output_len(n_leds);
+        pipeline.execute_command(command)?;
+
+        shared
+            .outputs
+            .as_mut()
+            .unwrap()
+            .resize(old_len, pipeline.output_len(n_leds));
+
+        Ok(())
+    })
+}
+
+// "bpm" replies the current tempo, "bpm <float>" sets it and "bpm range <min>..<max>"
+// limits the tempo accepted from taps
+fn bpm_command(args: &str) -> anyhow::Result<()> {
+    match args {
+        "" => {
+            let tempo = critical_section::with(|cs| {
+                let shared = SHARED.borrow_ref(cs);
+                let tap_info = shared.tap_info.as_ref()?;
+                Some((tap_info.bpm()?, tap_info.rate))
+            });
+            match tempo {
+                Some((bpm, rate)) => reply(format!("{:.1} BPM at {}x", bpm, rate)),
+                None => reply("No tempo set".into()),
+            }
+        }
+        args if args.starts_with("range ") => {
+            let (min_bpm, max_bpm) = parse_tuple::<f32>(&args[6..])?;
+            critical_section::with(|cs| {
+                SHARED
+                    .borrow_ref_mut(cs)
+                    .tap_info
+                    .get_or_insert_with(TapInfo::new)
+                    .set_bpm_range(min_bpm, max_bpm)
+            })?;
+        }
+        bpm => bpm_input(parse::<f32>(bpm)?)?,
+    }
+
+    Ok(())
+}
+
+// changes the time signature (e.g. "signature 6/8"), it is used from the next bar on
+fn set_signature(arg: &str) -> anyhow::Result<()> {
+    let signature = TimeSignature::try_from(arg)?;
+
+    critical_section::with(|cs| {
+        SHARED
+            .borrow_ref_mut(cs)
+            .tap_info
+            .get_or_insert_with(TapInfo::new)
+            .signature = signature
+    });
+
+    Ok(())
+}
+
+// "swing <50..=75>[ 16]" delays the off-beat 8ths (or 16ths), "swing off" plays straight
+fn set_swing(arg: &str) -> anyhow::Result<()> {
+    let swing = Swing::try_from(arg)?;
+
+    critical_section::with(|cs| {
+        SHARED
+            .borrow_ref_mut(cs)
+            .tap_info
+            .get_or_insert_with(TapInfo::new)
+            .swing = swing
     });
+
+    Ok(())
+}
+
+// "phrase <4|8|16|32>" sets the number of bars per phrase, used from the next bar on
+fn set_phrase_length(arg: &str) -> anyhow::Result<()> {
+    let bars = parse::<usize>(arg)?;
+
+    critical_section::with(|cs| {
+        SHARED
+            .borrow_ref_mut(cs)
+            .tap_info
+            .get_or_insert_with(TapInfo::new)
+            .set_phrase_length(bars)
+    })
+}
+
+// "seed <u32>" restarts all patterns, so the same seed replays the same show, "seed off"
+// goes back to the hardware RNG
+fn reseed(arg: &str) -> anyhow::Result<()> {
+    let seed = match arg {
+        "off" => None,
+        seed => Some(parse::<u32>(seed)?),
+    };
+    set_seed(seed);
+
+    critical_section::with(|cs| SHARED.borrow_ref_mut(cs).rgbs.as_mut().unwrap().reseed());
+
+    Ok(())
 }
 
 pub fn handle_wireless_input(request: &str) -> anyhow::Result<()> {
     match request {
         "beat" => beat_input(),
-        "half" => change_speed(0.5),
-        "double" => change_speed(2.0),
-        "stop" => critical_section::with(|cs| {
-            let mut shared = SHARED.borrow_ref_mut(cs);
-            shared.tap_info.as_mut().unwrap().is_stopped = true;
-        }),
+        "downbeat" => downbeat_input(),
+        "half" => change_rate(0.5)?,
+        "double" => change_rate(2.0)?,
+        "stop" => with_tempo(|tap_info| {
+            tap_info.is_stopped = true;
+            Ok(())
+        })?,
+        cmd if cmd.starts_with("rate ") => rate_command(&cmd[5..])?,
+        cmd if cmd.starts_with("order ") => configure_outputs("order", &cmd[6..])?,
+        cmd if cmd.starts_with("chip ") => configure_outputs("chip", &cmd[5..])?,
+        cmd if cmd.starts_with("white ") => configure_outputs("white", &cmd[6..])?,
+        cmd if cmd.starts_with("range ") => set_output_range(&cmd[6..])?,
+        cmd if cmd.starts_with("leds ") => set_n_leds(&cmd[5..])?,
+        cmd if cmd.starts_with("seed ") => reseed(&cmd[5..])?,
+        "bpm" => bpm_command("")?,
+        cmd if cmd.starts_with("signature ") => set_signature(&cmd[10..])?,
+        cmd if cmd.starts_with("swing ") => set_swing(&cmd[6..])?,
+        cmd if cmd.starts_with("phrase ") => set_phrase_length(&cmd[7..])?,
+        cmd if cmd.starts_with("nudge ") => nudge_input(parse::<f32>(&cmd[6..])?)?,
+        cmd if cmd.starts_with("bpm ") => bpm_command(&cmd[4..])?,
+        cmd if Pipeline::handles(cmd) => configure_pipeline(cmd)?,
         cmd => critical_section::with(|cs| {
             SHARED
                 .borrow_ref_mut(cs)
@@ -1,3 +1,3 @@
 pub mod ble;
 pub mod commands;
-pub mod random;
+pub use yalbir::util::random;
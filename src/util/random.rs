@@ -1,12 +1,77 @@
-use esp_hal::rng::Rng;
+use alloc::boxed::Box;
+use core::cell::{Cell, RefCell};
 
-use crate::RNG;
+use critical_section::Mutex;
 
-pub fn get_rng() -> Rng {
-    critical_section::with(|cs| RNG.borrow_ref(cs).unwrap())
+/// Source of random numbers for the patterns.
+///
+/// The hardware RNG gives a different show every time, a seeded `XorShift` replays
+/// the same random sequence (and runs off-device).
+pub trait RandomSource: Send + Sync {
+    fn random(&mut self) -> u32;
 }
 
-pub fn from_range(range: (usize, usize), rng: &mut Rng) -> usize {
+/// Small seedable PRNG (xorshift32).
+#[derive(Clone, Debug)]
+pub struct XorShift {
+    state: u32,
+}
+
+impl XorShift {
+    pub fn new(seed: u32) -> Self {
+        // the state must never be 0, otherwise only zeros are generated
+        Self {
+            state: if seed == 0 { 0x9e37_79b9 } else { seed },
+        }
+    }
+}
+
+impl RandomSource for XorShift {
+    fn random(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+}
+
+// seed used off-device, where no hardware RNG is registered
+const FALLBACK_SEED: u32 = 0x2545_f491;
+
+// if set, new random sources are seeded from this one instead of using the hardware RNG
+static SEEDS: Mutex<RefCell<Option<XorShift>>> = Mutex::new(RefCell::new(None));
+
+type CreateRng = fn() -> Box<dyn RandomSource>;
+
+// creates a random source on the hardware RNG, registered by the firmware
+static HARDWARE_RNG: Mutex<Cell<Option<CreateRng>>> = Mutex::new(Cell::new(None));
+
+/// Registers the function that creates random sources on the hardware RNG. Without it,
+/// unseeded random sources start from a fixed seed.
+pub fn set_hardware_rng(create: CreateRng) {
+    critical_section::with(|cs| HARDWARE_RNG.borrow(cs).set(Some(create)));
+}
+
+/// Returns a random source for a new pattern.
+pub fn get_rng() -> Box<dyn RandomSource> {
+    critical_section::with(|cs| match SEEDS.borrow_ref_mut(cs).as_mut() {
+        Some(seeds) => Box::new(XorShift::new(seeds.random())) as Box<dyn RandomSource>,
+        None => match HARDWARE_RNG.borrow(cs).get() {
+            Some(create) => create(),
+            None => Box::new(XorShift::new(FALLBACK_SEED)),
+        },
+    })
+}
+
+/// Makes all random sources created from now on deterministic (`Some(seed)`), or
+/// switches back to the hardware RNG (`None`).
+pub fn set_seed(seed: Option<u32>) {
+    critical_section::with(|cs| *SEEDS.borrow_ref_mut(cs) = seed.map(XorShift::new));
+}
+
+pub fn from_range(range: (usize, usize), rng: &mut dyn RandomSource) -> usize {
     assert!(range.1 >= range.0);
 
     if range.0 == range.1 {